@@ -0,0 +1,83 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use tokio::time::{sleep, Sleep};
+
+/// Request body that is sent either in one piece or as a sequence of
+/// fixed-size chunks with a delay between them.
+pub struct RequestBody {
+    data: Bytes,
+    chunk_size: usize,
+    delay: Duration,
+    pending: Option<Pin<Box<Sleep>>>,
+    written: Arc<OnceLock<Instant>>,
+}
+
+impl RequestBody {
+    /// A body sent in chunks of `chunk_size` bytes with `delay` between them.
+    /// A `chunk_size` of zero sends the body in one piece.
+    pub fn chunked(data: Bytes, chunk_size: usize, delay: Duration) -> Self {
+        RequestBody {
+            data,
+            chunk_size,
+            delay,
+            pending: None,
+            written: Arc::default(),
+        }
+    }
+
+    /// When the connection started writing the request, set once it first
+    /// inspects the body, which it does as it writes the HEADERS frame
+    pub fn written(&self) -> Arc<OnceLock<Instant>> {
+        self.written.clone()
+    }
+}
+
+impl Body for RequestBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        this.written.get_or_init(Instant::now);
+
+        if this.data.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        if let Some(pending) = &mut this.pending {
+            ready!(pending.as_mut().poll(cx));
+            this.pending = None;
+        }
+
+        let len = match this.chunk_size {
+            0 => this.data.len(),
+            size => size.min(this.data.len()),
+        };
+        let chunk = this.data.split_to(len);
+
+        if !this.data.is_empty() && !this.delay.is_zero() {
+            this.pending = Some(Box::pin(sleep(this.delay)));
+        }
+
+        Poll::Ready(Some(Ok(Frame::data(chunk))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.written.get_or_init(Instant::now);
+        self.data.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.written.get_or_init(Instant::now);
+        SizeHint::with_exact(self.data.len() as u64)
+    }
+}
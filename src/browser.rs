@@ -0,0 +1,80 @@
+use clap::ValueEnum;
+use hyper::header::{HeaderName, HeaderValue};
+
+/// Browser whose navigation request headers `--pretend-browser` sends
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Browser {
+    /// Chrome 129 on Windows
+    Chrome,
+    /// Firefox 131 on Windows
+    Firefox,
+}
+
+// Header sets of a top-level navigation, in the order the browsers send them.
+// Update them here when the presets fall behind current releases.
+const CHROME: &[(&str, &str)] = &[
+    (
+        "sec-ch-ua",
+        r#""Google Chrome";v="129", "Not=A?Brand";v="8", "Chromium";v="129""#,
+    ),
+    ("sec-ch-ua-mobile", "?0"),
+    ("sec-ch-ua-platform", r#""Windows""#),
+    ("upgrade-insecure-requests", "1"),
+    (
+        "user-agent",
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) \
+         Chrome/129.0.0.0 Safari/537.36",
+    ),
+    (
+        "accept",
+        "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,\
+         image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7",
+    ),
+    ("sec-fetch-site", "none"),
+    ("sec-fetch-mode", "navigate"),
+    ("sec-fetch-user", "?1"),
+    ("sec-fetch-dest", "document"),
+    ("accept-encoding", "gzip, deflate, br, zstd"),
+    ("accept-language", "en-US,en;q=0.9"),
+    ("priority", "u=0, i"),
+];
+
+const FIREFOX: &[(&str, &str)] = &[
+    (
+        "user-agent",
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:131.0) Gecko/20100101 Firefox/131.0",
+    ),
+    (
+        "accept",
+        "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,\
+         image/png,image/svg+xml,*/*;q=0.8",
+    ),
+    ("accept-language", "en-US,en;q=0.5"),
+    ("accept-encoding", "gzip, deflate, br, zstd"),
+    ("upgrade-insecure-requests", "1"),
+    ("sec-fetch-dest", "document"),
+    ("sec-fetch-mode", "navigate"),
+    ("sec-fetch-site", "none"),
+    ("sec-fetch-user", "?1"),
+    ("priority", "u=0, i"),
+    ("te", "trailers"),
+];
+
+impl Browser {
+    /// The headers of this browser's preset
+    pub fn headers(self) -> Vec<(HeaderName, HeaderValue)> {
+        let preset = match self {
+            Browser::Chrome => CHROME,
+            Browser::Firefox => FIREFOX,
+        };
+        preset
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+/// Resources used by this process so far
+pub struct ResourceUsage {
+    /// Peak resident set size in bytes
+    pub peak_rss: u64,
+    pub user_time: Duration,
+    pub system_time: Duration,
+}
+
+/// Query the resource usage of the current process, if the platform supports it.
+#[cfg(unix)]
+pub fn resource_usage() -> Option<ResourceUsage> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: getrusage only writes to the provided, properly sized struct
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+        usage.assume_init()
+    };
+
+    // macOS reports the peak RSS in bytes, other platforms in KiB
+    let peak_rss = if cfg!(target_os = "macos") {
+        usage.ru_maxrss as u64
+    } else {
+        usage.ru_maxrss as u64 * 1024
+    };
+    let time = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+
+    Some(ResourceUsage {
+        peak_rss,
+        user_time: time(usage.ru_utime),
+        system_time: time(usage.ru_stime),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn resource_usage() -> Option<ResourceUsage> {
+    None
+}
+
+/// Number of file descriptors currently open, where `/proc` provides it.
+pub fn open_fds() -> Option<usize> {
+    std::fs::read_dir("/proc/self/fd")
+        .ok()
+        // Don't count the descriptor used for reading the directory itself
+        .map(|entries| entries.count().saturating_sub(1))
+}
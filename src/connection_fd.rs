@@ -0,0 +1,59 @@
+use std::net::TcpStream;
+
+/// Take ownership of the inherited socket `fd` for `--connection-fd`,
+/// checking that it is a connected TCP stream.
+#[cfg(unix)]
+pub fn adopt(fd: i32) -> Result<TcpStream, anyhow::Error> {
+    use std::io;
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: F_GETFD only inspects the descriptor table
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        anyhow::bail!("--connection-fd {}: {}", fd, io::Error::last_os_error());
+    }
+
+    let mut kind: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: the kernel writes at most `len` bytes into `kind`
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            (&mut kind as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        anyhow::bail!(
+            "--connection-fd {} is not a socket: {}",
+            fd,
+            io::Error::last_os_error()
+        );
+    }
+    if kind != libc::SOCK_STREAM {
+        anyhow::bail!("--connection-fd {} is not a stream socket", fd);
+    }
+
+    // SAFETY: the descriptor is open and nothing else in this process uses it
+    let stream = unsafe { TcpStream::from_raw_fd(fd) };
+    // Fails for unconnected sockets and for non-IP families such as Unix sockets
+    if let Err(err) = stream.peer_addr() {
+        anyhow::bail!(
+            "--connection-fd {} is not a connected TCP socket: {}",
+            fd,
+            err
+        );
+    }
+    stream.set_nonblocking(true)?;
+
+    Ok(stream)
+}
+
+#[cfg(not(unix))]
+pub fn adopt(fd: i32) -> Result<TcpStream, anyhow::Error> {
+    anyhow::bail!(
+        "--connection-fd {}: inherited sockets are only supported on Unix",
+        fd
+    )
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::StatusCode;
+
+/// How the `--deadline` of a request is sent to the server
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum DeadlineFormat {
+    /// Milliseconds remaining, in the --deadline-header header
+    Relative,
+    /// Unix timestamp in milliseconds, in the --deadline-header header
+    Absolute,
+    /// gRPC `grpc-timeout` header, e.g. `250m`
+    GrpcTimeout,
+}
+
+/// Returned when the server reports that a request's deadline was exceeded.
+#[derive(Debug)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "server reported the deadline as exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Build the header announcing `deadline` from now on.
+pub fn header(
+    format: DeadlineFormat,
+    name: &HeaderName,
+    deadline: Duration,
+) -> Result<(HeaderName, HeaderValue), anyhow::Error> {
+    let millis = deadline.as_millis();
+    Ok(match format {
+        DeadlineFormat::Relative => (name.clone(), HeaderValue::from(millis as u64)),
+        DeadlineFormat::Absolute => {
+            let at = SystemTime::now().duration_since(UNIX_EPOCH)? + deadline;
+            (name.clone(), HeaderValue::from(at.as_millis() as u64))
+        }
+        DeadlineFormat::GrpcTimeout => (
+            HeaderName::from_static("grpc-timeout"),
+            HeaderValue::from_str(&format!("{}m", millis))?,
+        ),
+    })
+}
+
+/// Whether a response sheds a request for its `deadline`: the response
+/// signals a timeout, a 504 status or a gRPC `DEADLINE_EXCEEDED` status sent
+/// as a header (trailers are not read), and arrived `waited` after the
+/// deadline was set, once the deadline had passed. Timeouts signalled before
+/// then are not sheds, e.g. a 504 from a proxy's own timeout.
+pub fn exceeded(
+    status: StatusCode,
+    headers: &HeaderMap,
+    waited: Duration,
+    deadline: Duration,
+) -> bool {
+    let signalled = status == StatusCode::GATEWAY_TIMEOUT
+        || headers
+            .get("grpc-status")
+            .is_some_and(|value| value.as_bytes() == b"4");
+    signalled && waited >= deadline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEADLINE: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn sheds_need_the_deadline_to_have_passed() {
+        let none = HeaderMap::new();
+        let late = Duration::from_millis(150);
+        assert!(exceeded(StatusCode::GATEWAY_TIMEOUT, &none, late, DEADLINE));
+        assert!(exceeded(
+            StatusCode::GATEWAY_TIMEOUT,
+            &none,
+            DEADLINE,
+            DEADLINE
+        ));
+        let early = Duration::from_millis(20);
+        assert!(!exceeded(
+            StatusCode::GATEWAY_TIMEOUT,
+            &none,
+            early,
+            DEADLINE
+        ));
+        assert!(!exceeded(StatusCode::OK, &none, late, DEADLINE));
+    }
+
+    #[test]
+    fn grpc_deadline_exceeded_is_a_shed() {
+        let mut headers = HeaderMap::new();
+        headers.insert("grpc-status", HeaderValue::from_static("4"));
+        let late = Duration::from_millis(150);
+        assert!(exceeded(StatusCode::OK, &headers, late, DEADLINE));
+        headers.insert("grpc-status", HeaderValue::from_static("14"));
+        assert!(!exceeded(StatusCode::OK, &headers, late, DEADLINE));
+    }
+
+    #[test]
+    fn header_formats() {
+        let name = HeaderName::from_static("x-request-deadline");
+        let (sent, value) = header(DeadlineFormat::Relative, &name, DEADLINE).unwrap();
+        assert_eq!(
+            (sent.as_str(), value.to_str().unwrap()),
+            ("x-request-deadline", "100")
+        );
+        let (sent, value) = header(DeadlineFormat::GrpcTimeout, &name, DEADLINE).unwrap();
+        assert_eq!(
+            (sent.as_str(), value.to_str().unwrap()),
+            ("grpc-timeout", "100m")
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Share of the run a worker is given by the coordinator
+pub struct Assignment {
+    pub rate: f64,
+    pub total: usize,
+}
+
+/// Results a worker sends back to the coordinator once its share is done
+pub struct WorkerSummary {
+    pub requests: usize,
+    pub success_rate: f64,
+    pub achieved_rate: f64,
+    pub p99_latency: Duration,
+}
+
+// The protocol is one flat JSON object per line: the coordinator sends each
+// worker its assignment, the worker answers with its summary.
+
+impl Assignment {
+    fn encode(&self) -> String {
+        format!(r#"{{"rate":{},"total":{}}}"#, self.rate, self.total)
+    }
+
+    fn decode(line: &str) -> Result<Self, anyhow::Error> {
+        let fields = fields(line)?;
+        Ok(Assignment {
+            rate: field(&fields, "rate")?,
+            total: field(&fields, "total")? as usize,
+        })
+    }
+}
+
+impl WorkerSummary {
+    fn encode(&self) -> String {
+        format!(
+            r#"{{"requests":{},"success_rate":{},"achieved_rate":{},"p99_ns":{}}}"#,
+            self.requests,
+            self.success_rate,
+            self.achieved_rate,
+            self.p99_latency.as_nanos()
+        )
+    }
+
+    fn decode(line: &str) -> Result<Self, anyhow::Error> {
+        let fields = fields(line)?;
+        Ok(WorkerSummary {
+            requests: field(&fields, "requests")? as usize,
+            success_rate: field(&fields, "success_rate")?,
+            achieved_rate: field(&fields, "achieved_rate")?,
+            p99_latency: Duration::from_nanos(field(&fields, "p99_ns")? as u64),
+        })
+    }
+}
+
+/// Wait for `workers` workers to connect to `listen`, split `rate` and
+/// `total` evenly between them and collect their summaries, in the order
+/// the workers connected.
+pub async fn coordinate(
+    listen: &str,
+    workers: usize,
+    rate: f64,
+    total: usize,
+) -> Result<Vec<WorkerSummary>, anyhow::Error> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to listen on {}: {}", listen, err))?;
+    eprintln!(
+        "coordinator: waiting for {} worker(s) on {}",
+        workers, listen
+    );
+    let mut streams = Vec::with_capacity(workers);
+    while streams.len() < workers {
+        let (stream, peer) = listener.accept().await?;
+        eprintln!(
+            "coordinator: worker {} connected from {}",
+            streams.len(),
+            peer
+        );
+        streams.push(BufReader::new(stream));
+    }
+
+    // Hand out the shares only once everyone is there, so the workers start together
+    for (n, stream) in streams.iter_mut().enumerate() {
+        // The first workers take the remainder of an uneven split
+        let share = total / workers + usize::from(n < total % workers);
+        let assignment = Assignment {
+            rate: rate / workers as f64,
+            total: share,
+        };
+        stream
+            .get_mut()
+            .write_all(format!("{}\n", assignment.encode()).as_bytes())
+            .await?;
+    }
+
+    let mut summaries = Vec::with_capacity(workers);
+    for (n, stream) in streams.iter_mut().enumerate() {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("worker {} disconnected before reporting its results", n);
+        }
+        summaries.push(WorkerSummary::decode(&line)?);
+    }
+    Ok(summaries)
+}
+
+/// Connection of a worker to its coordinator
+pub struct Worker {
+    stream: BufReader<TcpStream>,
+}
+
+impl Worker {
+    /// Connect to the coordinator at `address` and wait for this worker's share.
+    pub async fn join(address: &str) -> Result<(Self, Assignment), anyhow::Error> {
+        let stream = TcpStream::connect(address).await.map_err(|err| {
+            anyhow::anyhow!("failed to connect to coordinator {}: {}", address, err)
+        })?;
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("coordinator {} closed the connection", address);
+        }
+        Ok((Worker { stream }, Assignment::decode(&line)?))
+    }
+
+    /// Send this worker's results to the coordinator.
+    pub async fn report(mut self, summary: &WorkerSummary) -> Result<(), anyhow::Error> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{}\n", summary.encode()).as_bytes())
+            .await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+/// Combined summary of the workers' results. Percentiles can't be merged
+/// from the workers' summaries, so the worst worker's p99 stands in for the
+/// overall one.
+pub fn combine(summaries: &[WorkerSummary]) -> WorkerSummary {
+    let requests: usize = summaries.iter().map(|s| s.requests).sum();
+    let successes: f64 = summaries
+        .iter()
+        .map(|s| s.success_rate * s.requests as f64)
+        .sum();
+    WorkerSummary {
+        requests,
+        success_rate: successes / requests.max(1) as f64,
+        achieved_rate: summaries.iter().map(|s| s.achieved_rate).sum(),
+        p99_latency: summaries
+            .iter()
+            .map(|s| s.p99_latency)
+            .max()
+            .unwrap_or_default(),
+    }
+}
+
+/// Split a flat JSON object of numbers into its fields
+fn fields(line: &str) -> Result<HashMap<&str, f64>, anyhow::Error> {
+    let body = line
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or_else(|| anyhow::anyhow!("malformed message: {}", line.trim()))?;
+    let mut fields = HashMap::new();
+    for pair in body.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (key, value) = pair
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("malformed field: {}", pair))?;
+        let key = key.trim().trim_matches('"');
+        let value = value
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("field {} is not a number: {}", key, value.trim()))?;
+        fields.insert(key, value);
+    }
+    Ok(fields)
+}
+
+fn field(fields: &HashMap<&str, f64>, name: &str) -> Result<f64, anyhow::Error> {
+    fields
+        .get(name)
+        .copied()
+        .ok_or_else(|| anyhow::anyhow!("message is missing field {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_parses_a_flat_object() {
+        let fields = fields(r#" { "rate": 12.5, "total":40 } "#).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(field(&fields, "rate").unwrap(), 12.5);
+        assert_eq!(field(&fields, "total").unwrap(), 40.0);
+        assert!(field(&fields, "missing").is_err());
+        assert!(super::fields("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn fields_rejects_malformed_messages() {
+        for line in [r#""rate":1"#, r#"{"rate"}"#, r#"{"rate":"fast"}"#, "{"] {
+            assert!(fields(line).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn assignment_round_trips() {
+        let decoded = Assignment::decode(
+            &Assignment {
+                rate: 33.25,
+                total: 7,
+            }
+            .encode(),
+        )
+        .unwrap();
+        assert_eq!(decoded.rate, 33.25);
+        assert_eq!(decoded.total, 7);
+    }
+
+    #[test]
+    fn worker_summary_round_trips() {
+        let summary = WorkerSummary {
+            requests: 100,
+            success_rate: 99.5,
+            achieved_rate: 49.75,
+            p99_latency: Duration::from_nanos(12_345_678),
+        };
+        let decoded = WorkerSummary::decode(&summary.encode()).unwrap();
+        assert_eq!(decoded.requests, 100);
+        assert_eq!(decoded.success_rate, 99.5);
+        assert_eq!(decoded.achieved_rate, 49.75);
+        assert_eq!(decoded.p99_latency, summary.p99_latency);
+    }
+
+    #[test]
+    fn combine_weights_success_by_requests() {
+        let summary = |requests, success_rate, p99_ms| WorkerSummary {
+            requests,
+            success_rate,
+            achieved_rate: 10.0,
+            p99_latency: Duration::from_millis(p99_ms),
+        };
+        let combined = combine(&[summary(300, 100.0, 5), summary(100, 60.0, 9)]);
+        assert_eq!(combined.requests, 400);
+        assert_eq!(combined.success_rate, 90.0);
+        assert_eq!(combined.achieved_rate, 20.0);
+        assert_eq!(combined.p99_latency, Duration::from_millis(9));
+        assert_eq!(combine(&[]).success_rate, 0.0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Distinct messages kept before further new ones are only counted
+const MAX_MESSAGES: usize = 1000;
+
+/// Deduplicated failure messages with how often each occurred
+#[derive(Default)]
+pub struct ErrorMessages {
+    counts: HashMap<String, usize>,
+    /// Occurrences of messages not seen before the map filled up
+    overflow: usize,
+}
+
+impl ErrorMessages {
+    /// Count the message of `err`, including its causes.
+    pub fn record(&mut self, err: &anyhow::Error) {
+        let message = normalize(&format!("{:#}", err));
+        if let Some(count) = self.counts.get_mut(&message) {
+            *count += 1;
+        } else if self.counts.len() < MAX_MESSAGES {
+            self.counts.insert(message, 1);
+        } else {
+            self.overflow += 1;
+        }
+    }
+
+    /// The `n` most frequent messages, most frequent first
+    pub fn top(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut messages: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(message, count)| (message.as_str(), *count))
+            .collect();
+        messages.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        messages.truncate(n);
+        messages
+    }
+
+    /// Number of distinct messages, not counting overflowed ones
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn overflow(&self) -> usize {
+        self.overflow
+    }
+}
+
+/// Replace the IP addresses and socket addresses in `message`, e.g. the
+/// local port of a connection, so messages differing only in them collapse
+fn normalize(message: &str) -> String {
+    message
+        .split(' ')
+        .map(|word| {
+            let trimmed = word
+                .trim_start_matches(['(', '"', '\''])
+                .trim_end_matches([')', ',', ';', ':', '.', '"', '\'']);
+            if trimmed.parse::<SocketAddr>().is_ok() || trimmed.parse::<IpAddr>().is_ok() {
+                word.replace(trimmed, "<addr>")
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_replaces_addresses() {
+        assert_eq!(
+            normalize("connect to 10.0.0.7:8080 failed (from 127.0.0.1:53122)"),
+            "connect to <addr> failed (from <addr>)"
+        );
+        assert_eq!(normalize("host [::1]:443 refused"), "host <addr> refused");
+        assert_eq!(normalize("peer \"::1\" closed"), "peer \"<addr>\" closed");
+        assert_eq!(normalize("status 503: try again"), "status 503: try again");
+    }
+
+    #[test]
+    fn record_counts_messages_differing_only_in_addresses_together() {
+        let mut messages = ErrorMessages::default();
+        messages.record(&anyhow::anyhow!("reset by 10.0.0.1:4000"));
+        messages.record(&anyhow::anyhow!("reset by 10.0.0.2:4001"));
+        messages.record(&anyhow::anyhow!("timed out"));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages.top(5), [("reset by <addr>", 2), ("timed out", 1)]);
+        assert_eq!(messages.top(1).len(), 1);
+    }
+
+    #[test]
+    fn record_includes_the_causes() {
+        let mut messages = ErrorMessages::default();
+        messages.record(&anyhow::anyhow!("refused").context("connecting"));
+        assert_eq!(messages.top(1), [("connecting: refused", 1)]);
+    }
+
+    #[test]
+    fn new_messages_beyond_the_limit_only_count() {
+        let mut messages = ErrorMessages::default();
+        for n in 0..MAX_MESSAGES + 3 {
+            messages.record(&anyhow::anyhow!("error {}", n));
+        }
+        messages.record(&anyhow::anyhow!("error 0"));
+        assert_eq!(messages.len(), MAX_MESSAGES);
+        assert_eq!(messages.overflow(), 3);
+        assert_eq!(messages.top(1), [("error 0", 2)]);
+    }
+}
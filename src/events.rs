@@ -0,0 +1,104 @@
+use std::io::{BufWriter, Write};
+use std::time::Duration;
+
+use clap::ValueEnum;
+use hyper::StatusCode;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+
+use crate::output;
+
+/// Format of the per-request events written by `--events`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum EventFormat {
+    /// One JSON object per line
+    Jsonl,
+}
+
+/// Spawn a task writing the lines it is sent to `out`, e.g. stdout. Output
+/// is buffered and flushed whenever no further lines are waiting; the task
+/// finishes once every sender has been dropped.
+pub fn spawn_writer(out: impl Write + Send + 'static) -> (UnboundedSender<String>, JoinHandle<()>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+
+    let writer = tokio::spawn(async move {
+        let mut out = BufWriter::new(out);
+        while let Some(line) = receiver.recv().await {
+            // A closed output must not fail the run itself
+            let _ = writeln!(out, "{}", line);
+            if receiver.is_empty() {
+                let _ = out.flush();
+            }
+        }
+        let _ = out.flush();
+    });
+
+    (sender, writer)
+}
+
+/// Event for a completed request
+pub fn success(
+    index: usize,
+    latency: Duration,
+    status: StatusCode,
+    trace_id: Option<u128>,
+) -> String {
+    format!(
+        r#"{{"index":{},"latency_ms":{:.3},"status":{},"ok":true{}}}"#,
+        index,
+        latency.as_secs_f64() * 1000.0,
+        status.as_u16(),
+        trace_field(trace_id)
+    )
+}
+
+/// Event for a failed request, with the category it is reported under
+pub fn failure(index: usize, category: &str, trace_id: Option<u128>) -> String {
+    format!(
+        r#"{{"index":{},"latency_ms":null,"status":null,"ok":false,"error":{}{}}}"#,
+        index,
+        output::string(category),
+        trace_field(trace_id)
+    )
+}
+
+/// `"trace_id"` member for requests sent with --trace-context
+fn trace_field(trace_id: Option<u128>) -> String {
+    match trace_id {
+        Some(trace_id) => format!(r#","trace_id":"{:032x}""#, trace_id),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pretty_json;
+
+    #[test]
+    fn success_line() {
+        assert_eq!(
+            success(3, Duration::from_micros(1_482), StatusCode::OK, None),
+            r#"{"index":3,"latency_ms":1.482,"status":200,"ok":true}"#
+        );
+        assert_eq!(
+            success(
+                4,
+                Duration::from_millis(2),
+                StatusCode::NOT_FOUND,
+                Some(0xab)
+            ),
+            r#"{"index":4,"latency_ms":2.000,"status":404,"ok":true,"trace_id":"000000000000000000000000000000ab"}"#
+        );
+    }
+
+    #[test]
+    fn failure_category_is_escaped() {
+        let line = failure(7, r#"script error: "quoted" \ path"#, Some(1));
+        assert_eq!(
+            line,
+            r#"{"index":7,"latency_ms":null,"status":null,"ok":false,"error":"script error: \"quoted\" \\ path","trace_id":"00000000000000000000000000000001"}"#
+        );
+        assert!(pretty_json::pretty(&line).is_some(), "{}", line);
+    }
+}
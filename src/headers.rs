@@ -0,0 +1,239 @@
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Parse a `Name: Value` header as given to `--header`.
+pub fn parse(header: &str) -> Result<(HeaderName, HeaderValue), anyhow::Error> {
+    let Some((name, value)) = header.split_once(':') else {
+        anyhow::bail!("invalid header `{}`, expected `Name: Value`", header);
+    };
+
+    Ok((
+        HeaderName::from_bytes(name.trim().as_bytes())?,
+        HeaderValue::from_str(value.trim())?,
+    ))
+}
+
+/// Load headers from a file with one `Name: Value` per line. Blank lines and
+/// lines starting with `#` are ignored.
+pub fn load(path: &Path) -> Result<Vec<(HeaderName, HeaderValue)>, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))?;
+
+    let mut headers = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let header = parse(line)
+            .map_err(|err| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, err))?;
+        headers.push(header);
+    }
+
+    Ok(headers)
+}
+
+/// How requests pick from the `--header-set-file` sets
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum HeaderSetOrder {
+    /// Cycle through the sets in file order
+    RoundRobin,
+    /// Pick a set at random, seeded by --seed
+    Random,
+}
+
+/// A block of headers from a `--header-set-file`
+#[derive(Debug)]
+pub struct HeaderSet {
+    /// Line the set starts on, to tell the sets apart in the summary
+    pub line: usize,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Load header sets from a file of `Name: Value` lines, where blank lines
+/// separate the sets. Lines starting with `#` are ignored. Every header is
+/// validated, so a bad set fails at startup rather than mid-run.
+pub fn load_sets(path: &Path) -> Result<Vec<HeaderSet>, anyhow::Error> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))?;
+
+    let mut sets = Vec::new();
+    let mut current: Option<HeaderSet> = None;
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            sets.extend(current.take());
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let header = parse(line)
+            .map_err(|err| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, err))?;
+        current
+            .get_or_insert_with(|| HeaderSet {
+                line: number + 1,
+                headers: Vec::new(),
+            })
+            .headers
+            .push(header);
+    }
+    sets.extend(current);
+
+    if sets.is_empty() {
+        anyhow::bail!("header set file {} contains no headers", path.display());
+    }
+    Ok(sets)
+}
+
+/// Collect `headers` into a map, preserving their order. With `sensitive`,
+/// every value is marked so HPACK never adds it to the dynamic table.
+pub fn to_map(headers: &[(HeaderName, HeaderValue)], sensitive: bool) -> HeaderMap {
+    let mut map = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        let mut value = value.clone();
+        value.set_sensitive(sensitive);
+        map.append(name.clone(), value);
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Write `contents` to a file of its own in the temp directory
+    fn header_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "http2-load-test-headers-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn pairs(headers: &[(HeaderName, HeaderValue)]) -> Vec<(&str, &str)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.to_str().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_header() {
+        let (name, value) = parse("  X-Request-Source :  load test ").unwrap();
+        assert_eq!(
+            (name.as_str(), value.to_str().unwrap()),
+            ("x-request-source", "load test")
+        );
+        // Only the first colon separates the name
+        let (name, value) = parse("Referer: http://localhost:8080/a").unwrap();
+        assert_eq!(
+            (name.as_str(), value.to_str().unwrap()),
+            ("referer", "http://localhost:8080/a")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        assert_eq!(
+            parse("no colon").unwrap_err().to_string(),
+            "invalid header `no colon`, expected `Name: Value`"
+        );
+        assert!(parse(": value").is_err());
+        assert!(parse("bad name: value").is_err());
+        assert!(parse("name: bad\u{7f}value").is_err());
+    }
+
+    #[test]
+    fn loads_a_file() {
+        let path = header_file(
+            "load",
+            "# Captured from the browser\n\nAccept: */*\n  # indented comment\nCookie: a=1; b=2:3\n\n",
+        );
+        let headers = load(&path).unwrap();
+        assert_eq!(
+            pairs(&headers),
+            [("accept", "*/*"), ("cookie", "a=1; b=2:3")]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let path = header_file("bad-line", "Accept: */*\n# comment\nnot a header\n");
+        let err = load(&path).unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "{}:3: invalid header `not a header`, expected `Name: Value`",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let missing = std::env::temp_dir().join("http2-load-test-headers-missing");
+        let err = load(&missing).unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("failed to read {}: ", missing.display())),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn splits_sets_on_blank_lines() {
+        let path = header_file(
+            "sets",
+            "\n\n# mobile client\nUser-Agent: app/1.0\nAccept: */*\n\n\n\
+             User-Agent: curl/8\n# between sets\n   \nCookie: a=1\n\n",
+        );
+        let sets = load_sets(&path).unwrap();
+        let sets: Vec<_> = sets
+            .iter()
+            .map(|set| (set.line, pairs(&set.headers)))
+            .collect();
+        assert_eq!(
+            sets,
+            [
+                (4, vec![("user-agent", "app/1.0"), ("accept", "*/*")]),
+                (8, vec![("user-agent", "curl/8")]),
+                (11, vec![("cookie", "a=1")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_empty_set_files() {
+        for (name, contents) in [
+            ("empty", ""),
+            ("blank", "\n  \n"),
+            ("comments", "# a\n\n# b\n"),
+        ] {
+            let path = header_file(name, contents);
+            assert_eq!(
+                load_sets(&path).unwrap_err().to_string(),
+                format!("header set file {} contains no headers", path.display())
+            );
+        }
+    }
+
+    #[test]
+    fn set_errors_name_the_line() {
+        let path = header_file("bad-set", "Accept: */*\n\nCookie a=1\n");
+        assert_eq!(
+            load_sets(&path).unwrap_err().to_string(),
+            format!(
+                "{}:3: invalid header `Cookie a=1`, expected `Name: Value`",
+                path.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::body::Bytes;
+use hyper::header::HeaderMap;
+use hyper::{Method, Uri};
+use tokio::task::JoinHandle;
+
+use crate::body::RequestBody;
+use crate::percentiles::{PercentileConfig, Percentiles};
+use crate::{build_request, connect, make_request, DrainOptions, LatencyFrom};
+
+/// Results of the `--health-url` probes
+pub struct HealthStats {
+    pub probes: usize,
+    /// Probes answered with a 2xx status
+    pub healthy: usize,
+    /// Latency of the healthy probes
+    pub latencies: Percentiles,
+}
+
+/// Spawn a task that sends a GET request to `uri` every `interval` on its own
+/// connection until it is aborted, reconnecting after failed probes.
+pub fn spawn(
+    uri: Uri,
+    interval: Duration,
+    max_body_size: usize,
+    percentiles: PercentileConfig,
+) -> (Arc<Mutex<HealthStats>>, JoinHandle<()>) {
+    let stats = Arc::new(Mutex::new(HealthStats {
+        probes: 0,
+        healthy: 0,
+        latencies: Percentiles::new(percentiles),
+    }));
+    let drain = DrainOptions::whole(max_body_size, false);
+
+    let task_stats = stats.clone();
+    let task = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut sender = None;
+        loop {
+            ticker.tick().await;
+            if sender.is_none() {
+                sender = connect(&uri, None).await.ok();
+            }
+
+            let result = match &mut sender {
+                Some(sender) => {
+                    let body = RequestBody::chunked(Bytes::new(), 0, Duration::ZERO);
+                    match build_request(Method::GET, uri.clone(), HeaderMap::new(), body) {
+                        Ok(req) => {
+                            make_request(
+                                sender,
+                                req,
+                                Arc::new(AtomicUsize::new(0)),
+                                drain.clone(),
+                                Instant::now(),
+                                LatencyFrom::Body,
+                            )
+                            .await
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                None => Err(anyhow::anyhow!("failed to connect")),
+            };
+
+            let mut stats = task_stats.lock().unwrap();
+            stats.probes += 1;
+            match result {
+                Ok(sample) if sample.status.is_success() => {
+                    stats.healthy += 1;
+                    stats.latencies.record(sample.duration);
+                }
+                Ok(_) => {}
+                Err(_) => sender = None,
+            }
+        }
+    });
+
+    (stats, task)
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Initial flow-control window of an HTTP/2 stream unless the peer raises it
+const DEFAULT_WINDOW: usize = 65_535;
+
+/// Concurrent stream limit many servers advertise
+const COMMON_STREAM_LIMIT: f64 = 100.0;
+
+/// Metrics of a finished run that the hints are derived from
+pub struct Observations<'a> {
+    pub target_rate: f64,
+    pub achieved_rate: f64,
+    pub average_in_flight: f64,
+    pub connections: usize,
+    pub max_concurrency: Option<usize>,
+    pub median_latency: Duration,
+    pub p99_latency: Duration,
+    pub largest_request_body: usize,
+    pub failures: &'a BTreeMap<&'static str, usize>,
+    /// Share of the available cores the process kept busy, if known
+    pub cpu_utilization: Option<f64>,
+}
+
+/// Guess at likely bottlenecks from the metrics of a run. The rules only fire
+/// on clear signals and may still be wrong, so they are reported as hints.
+pub fn hints(o: &Observations) -> Vec<String> {
+    let mut hints = Vec::new();
+
+    // --max-concurrency already prints its own note when it binds
+    let concurrency_bound = o
+        .max_concurrency
+        .is_some_and(|max| o.average_in_flight >= 0.9 * max as f64);
+    if o.achieved_rate < 0.9 * o.target_rate && !concurrency_bound {
+        match o.cpu_utilization {
+            Some(cpu) if cpu >= 0.9 => hints.push(format!(
+                "the client fell behind the target rate with its CPU {:.0}% busy; the load \
+                 generator is likely the bottleneck, not the server",
+                cpu * 100.0
+            )),
+            _ => hints.push(
+                "the client fell behind the target rate without saturating its CPU; timer \
+                 resolution limits a single pacer at high rates, so results under-load the server"
+                    .to_string(),
+            ),
+        }
+    }
+
+    let per_connection = o.average_in_flight / o.connections.max(1) as f64;
+    if per_connection > COMMON_STREAM_LIMIT {
+        hints.push(format!(
+            "{:.0} requests were in flight per connection on average; servers commonly allow \
+             {} concurrent streams, so more --connections may raise throughput",
+            per_connection, COMMON_STREAM_LIMIT
+        ));
+    }
+
+    if o.failures.contains_key("stream reset: REFUSED_STREAM") {
+        hints.push(
+            "the server refused streams over its concurrent stream limit; spread the load \
+             over more --connections or lower --max-concurrency"
+                .to_string(),
+        );
+    }
+
+    if o.largest_request_body > DEFAULT_WINDOW {
+        hints.push(format!(
+            "request bodies of up to {} bytes exceed the {} byte initial HTTP/2 flow-control \
+             window; unless the server advertises a larger one, uploads wait on WINDOW_UPDATE \
+             round trips",
+            o.largest_request_body, DEFAULT_WINDOW
+        ));
+    }
+
+    if !o.median_latency.is_zero() && o.p99_latency > o.median_latency * 10 {
+        hints.push(
+            "p99 latency is more than 10x the median; a long tail usually points at \
+             server-side queuing, pauses or packet loss (see --tcp-info)"
+                .to_string(),
+        );
+    }
+
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_FAILURES: &BTreeMap<&str, usize> = &BTreeMap::new();
+
+    /// A run that kept up with its target without any warning signs
+    fn healthy() -> Observations<'static> {
+        Observations {
+            target_rate: 1000.0,
+            achieved_rate: 995.0,
+            average_in_flight: 20.0,
+            connections: 1,
+            max_concurrency: None,
+            median_latency: Duration::from_millis(20),
+            p99_latency: Duration::from_millis(80),
+            largest_request_body: 1024,
+            failures: NO_FAILURES,
+            cpu_utilization: Some(0.3),
+        }
+    }
+
+    /// The hints for `o`, expecting exactly one that contains `expected`
+    fn only_hint(o: &Observations, expected: &str) {
+        let hints = hints(o);
+        assert_eq!(hints.len(), 1, "{:?}", hints);
+        assert!(hints[0].contains(expected), "{:?}", hints);
+    }
+
+    #[test]
+    fn healthy_run_has_no_hints() {
+        assert!(hints(&healthy()).is_empty());
+    }
+
+    #[test]
+    fn fell_behind_with_a_busy_cpu() {
+        let o = Observations {
+            achieved_rate: 600.0,
+            cpu_utilization: Some(0.95),
+            ..healthy()
+        };
+        only_hint(&o, "with its CPU 95% busy");
+    }
+
+    #[test]
+    fn fell_behind_without_a_busy_cpu() {
+        for cpu_utilization in [Some(0.4), None] {
+            let o = Observations {
+                achieved_rate: 600.0,
+                cpu_utilization,
+                ..healthy()
+            };
+            only_hint(&o, "without saturating its CPU");
+        }
+    }
+
+    #[test]
+    fn binding_concurrency_cap_explains_falling_behind() {
+        let capped = Observations {
+            achieved_rate: 600.0,
+            average_in_flight: 19.5,
+            max_concurrency: Some(20),
+            ..healthy()
+        };
+        assert!(hints(&capped).is_empty(), "{:?}", hints(&capped));
+        // A cap the run never got close to doesn't
+        let loose = Observations {
+            max_concurrency: Some(100),
+            ..capped
+        };
+        only_hint(&loose, "fell behind the target rate");
+    }
+
+    #[test]
+    fn too_many_streams_per_connection() {
+        let o = Observations {
+            average_in_flight: 250.0,
+            connections: 2,
+            ..healthy()
+        };
+        only_hint(&o, "125 requests were in flight per connection");
+        let spread = Observations {
+            connections: 4,
+            ..o
+        };
+        assert!(hints(&spread).is_empty());
+    }
+
+    #[test]
+    fn refused_streams() {
+        let refused = BTreeMap::from([("stream reset: REFUSED_STREAM", 3)]);
+        only_hint(
+            &Observations {
+                failures: &refused,
+                ..healthy()
+            },
+            "refused streams",
+        );
+        let other = BTreeMap::from([("request error", 3)]);
+        assert!(hints(&Observations {
+            failures: &other,
+            ..healthy()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn bodies_larger_than_the_window() {
+        let o = Observations {
+            largest_request_body: DEFAULT_WINDOW + 1,
+            ..healthy()
+        };
+        only_hint(&o, "65536 bytes exceed the 65535 byte");
+        let fits = Observations {
+            largest_request_body: DEFAULT_WINDOW,
+            ..healthy()
+        };
+        assert!(hints(&fits).is_empty());
+    }
+
+    #[test]
+    fn long_tail() {
+        let o = Observations {
+            p99_latency: Duration::from_millis(201),
+            ..healthy()
+        };
+        only_hint(&o, "more than 10x the median");
+        // Without a median there is nothing to compare against
+        let unmeasured = Observations {
+            median_latency: Duration::ZERO,
+            ..o
+        };
+        assert!(hints(&unmeasured).is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_TYPE, HOST};
+use hyper::{Method, Request, Uri};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::Report;
+
+/// Time a single push may take, from connecting to the response
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes of an error response quoted in the push failure
+const ERROR_BODY_LIMIT: usize = 200;
+
+/// A `--tag KEY=VALUE` added to every point
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Tag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !value.is_empty() => Ok(Tag {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("expected KEY=VALUE, got {:?}", s)),
+        }
+    }
+}
+
+/// The line protocol point for the final `report` of a run
+pub fn summary_line(measurement: &str, tags: &[Tag], report: &Report) -> String {
+    line(
+        measurement,
+        tags,
+        &[
+            ("target_rate", report.target_rate),
+            ("achieved_rate", report.achieved_rate),
+            ("success_rate", report.success_rate),
+            ("p99_latency_ms", report.p99_latency.as_secs_f64() * 1000.0),
+        ],
+    )
+}
+
+/// A line protocol point of `fields` stamped with the current time.
+/// Fields that aren't finite are left out, as line protocol has no way to
+/// write them.
+pub fn line(measurement: &str, tags: &[Tag], fields: &[(&str, f64)]) -> String {
+    let mut line = escape(measurement, &[',', ' ']);
+    for tag in tags {
+        let _ = write!(
+            line,
+            ",{}={}",
+            escape(&tag.key, &[',', '=', ' ']),
+            escape(&tag.value, &[',', '=', ' '])
+        );
+    }
+    let mut separator = ' ';
+    for &(name, value) in fields.iter().filter(|(_, value)| value.is_finite()) {
+        let _ = write!(
+            line,
+            "{}{}={}",
+            separator,
+            escape(name, &[',', '=', ' ']),
+            value
+        );
+        separator = ',';
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let _ = write!(line, " {}", timestamp);
+    line
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == '\\' || special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// POST `lines` to the InfluxDB write endpoint `url` over HTTP/1.1, which
+/// InfluxDB expects, and fail unless it answers with a 2xx status.
+pub async fn push(url: &Uri, lines: String) -> Result<(), anyhow::Error> {
+    timeout(PUSH_TIMEOUT, try_push(url, lines))
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {:?}", PUSH_TIMEOUT))?
+}
+
+async fn try_push(url: &Uri, lines: String) -> Result<(), anyhow::Error> {
+    let authority = url
+        .authority()
+        .ok_or_else(|| anyhow::anyhow!("{} has no host", url))?;
+    let port = authority.port_u16().unwrap_or(80);
+    let stream = TcpStream::connect((authority.host(), port)).await?;
+
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::task::spawn(conn);
+
+    let path = url.path_and_query().map_or("/", |pq| pq.as_str());
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(path)
+        .header(HOST, authority.as_str())
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Full::new(Bytes::from(lines)))?;
+    let res = sender.send_request(req).await?;
+    let status = res.status();
+    if !status.is_success() {
+        let body = res.into_body().collect().await?.to_bytes();
+        let body = String::from_utf8_lossy(&body[..body.len().min(ERROR_BODY_LIMIT)]);
+        anyhow::bail!("{} answered {}: {}", url, status, body.trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(key: &str, value: &str) -> Tag {
+        Tag {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// `line` without its timestamp, checking the timestamp is one
+    fn without_timestamp(line: &str) -> &str {
+        let (point, timestamp) = line.rsplit_once(' ').unwrap();
+        assert!(timestamp.parse::<u128>().unwrap() > 0, "{}", line);
+        point
+    }
+
+    #[test]
+    fn point_layout() {
+        let line = line(
+            "load",
+            &[tag("env", "staging"), tag("run", "7")],
+            &[("rate", 99.5), ("successes", 12.0)],
+        );
+        assert_eq!(
+            without_timestamp(&line),
+            "load,env=staging,run=7 rate=99.5,successes=12"
+        );
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        let line = line(
+            "load test,v2=x",
+            &[tag("team name", "a=b, c\\d")],
+            &[("p99 ms,=", 1.0)],
+        );
+        assert_eq!(
+            without_timestamp(&line),
+            r"load\ test\,v2=x,team\ name=a\=b\,\ c\\d p99\ ms\,\==1"
+        );
+    }
+
+    #[test]
+    fn skips_fields_that_are_not_finite() {
+        let line = line(
+            "load",
+            &[],
+            &[
+                ("target_rate", f64::NAN),
+                ("achieved_rate", 0.25),
+                ("x", f64::INFINITY),
+            ],
+        );
+        assert_eq!(without_timestamp(&line), "load achieved_rate=0.25");
+    }
+
+    #[test]
+    fn parses_tags() {
+        let parsed: Tag = "env=prod=eu".parse().unwrap();
+        assert_eq!(
+            (parsed.key.as_str(), parsed.value.as_str()),
+            ("env", "prod=eu")
+        );
+        for invalid in ["env", "=prod", "env=", ""] {
+            assert_eq!(
+                invalid.parse::<Tag>().unwrap_err(),
+                format!("expected KEY=VALUE, got {:?}", invalid)
+            );
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+
+/// Unit latencies are printed in
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum LatencyUnit {
+    /// Pick a unit per value (e.g. `1.62ms`, `850.00µs`)
+    Auto,
+    /// Milliseconds
+    Ms,
+    /// Microseconds
+    Us,
+    /// Nanoseconds
+    Ns,
+}
+
+/// How latencies are rendered in the summary
+#[derive(Clone, Copy)]
+pub struct LatencyFormat {
+    pub unit: LatencyUnit,
+    /// Digits after the decimal point
+    pub precision: usize,
+}
+
+impl LatencyFormat {
+    pub fn format(&self, latency: Duration) -> String {
+        let precision = self.precision;
+        match self.unit {
+            LatencyUnit::Auto => format!("{:.*?}", precision, latency),
+            LatencyUnit::Ms => format!("{:.*}ms", precision, latency.as_secs_f64() * 1e3),
+            LatencyUnit::Us => format!("{:.*}µs", precision, latency.as_secs_f64() * 1e6),
+            LatencyUnit::Ns => format!("{:.*}ns", precision, latency.as_secs_f64() * 1e9),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(unit: LatencyUnit, precision: usize, latency: Duration) -> String {
+        LatencyFormat { unit, precision }.format(latency)
+    }
+
+    #[test]
+    fn auto_picks_a_unit_per_value() {
+        let auto = |latency| format(LatencyUnit::Auto, 2, latency);
+        assert_eq!(auto(Duration::from_nanos(850)), "850.00ns");
+        assert_eq!(auto(Duration::from_nanos(1_500)), "1.50µs");
+        assert_eq!(auto(Duration::from_micros(1_620)), "1.62ms");
+        assert_eq!(auto(Duration::from_millis(2_500)), "2.50s");
+        assert_eq!(auto(Duration::ZERO), "0.00ns");
+    }
+
+    #[test]
+    fn fixed_units() {
+        let sub_micro = Duration::from_nanos(850);
+        let seconds = Duration::from_millis(12_345);
+        assert_eq!(format(LatencyUnit::Ms, 3, sub_micro), "0.001ms");
+        assert_eq!(format(LatencyUnit::Ms, 0, seconds), "12345ms");
+        assert_eq!(format(LatencyUnit::Us, 2, sub_micro), "0.85µs");
+        assert_eq!(format(LatencyUnit::Us, 0, seconds), "12345000µs");
+        assert_eq!(format(LatencyUnit::Ns, 0, sub_micro), "850ns");
+        assert_eq!(format(LatencyUnit::Ns, 1, seconds), "12345000000.0ns");
+    }
+
+    #[test]
+    fn precision_sets_the_digits() {
+        let latency = Duration::from_micros(1_234_567);
+        assert_eq!(format(LatencyUnit::Auto, 0, latency), "1s");
+        assert_eq!(format(LatencyUnit::Auto, 4, latency), "1.2346s");
+        assert_eq!(format(LatencyUnit::Ms, 1, latency), "1234.6ms");
+    }
+}
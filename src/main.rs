@@ -1,55 +1,1773 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use futures::stream::{FuturesUnordered, StreamExt};
-use http_body_util::Empty;
+use http_body_util::BodyExt;
 use hyper::body::Bytes;
-use hyper::{Request, Uri};
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper::client::conn::http2::SendRequest;
+use hyper::ext::Protocol;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::http::uri::{Authority, PathAndQuery};
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_util::rt::TokioExecutor;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use tokio::net::TcpStream;
-use tokio::time::sleep;
+use tokio::runtime::Runtime;
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, sleep_until, timeout, timeout_at};
 
-#[derive(Parser)]
+use crate::body::RequestBody;
+use crate::browser::Browser;
+use crate::deadline::{DeadlineExceeded, DeadlineFormat};
+use crate::events::EventFormat;
+use crate::headers::HeaderSetOrder;
+use crate::influxdb::Tag;
+use crate::latency::{LatencyFormat, LatencyUnit};
+use crate::output::{ErrorKind, OutputFormat, RunStats};
+use crate::percentiles::{PercentileConfig, PercentileEngine, Percentiles};
+use crate::priority::PriorityClass;
+use crate::response_size::SizeSummary;
+use crate::soak::SoakStats;
+use crate::success::SuccessExpr;
+use crate::synthetic::LatencyDistribution;
+use crate::tally::{RequestInfo, Tally};
+use crate::tcp_info::{TcpInfoStats, Watched};
+use crate::token_bucket::TokenBucket;
+
+mod body;
+mod browser;
+mod client_stats;
+mod connection_fd;
+mod deadline;
+mod distributed;
+mod error_messages;
+mod events;
+mod headers;
+mod health;
+mod hints;
+mod influxdb;
+mod latency;
+mod multipart;
+mod output;
+mod percentiles;
+mod pretty_json;
+mod priority;
+#[cfg(feature = "profile")]
+mod profile;
+mod replay;
+mod report;
+mod response_size;
+mod schedule;
+#[cfg(feature = "script")]
+mod script;
+mod slowest;
+mod soak;
+mod success;
+mod synthetic;
+mod tally;
+mod tcp_info;
+mod token_bucket;
+mod trace_context;
+mod wire_timing;
+
+#[derive(Clone, Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     /// Server address in the format hostname:port
-    address: String,
+    #[arg(required_unless_present_any = ["host", "synthetic"], conflicts_with = "host")]
+    address: Option<String>,
+
+    /// Server to send requests to in the format hostname:port, as an
+    /// alternative to the address when only --path changes between runs
+    #[arg(long, value_name = "HOST:PORT")]
+    host: Option<String>,
+
+    /// With --host, the path and query of the requests [default: /]
+    #[arg(long, requires = "host")]
+    path: Option<PathAndQuery>,
+
+    /// Target request rate (requests per second)
+    #[arg(short, long, default_value_t = 1.0)]
+    rate: f64,
+
+    /// Total number of requests to execute
+    #[arg(short, long, default_value_t = 1)]
+    total: usize,
+
+    /// Keep sending requests until this many succeed, instead of a fixed --total
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["total", "replay", "rate_schedule", "find_max_rate", "connect_only", "stop_on_first_success"]
+    )]
+    target_successes: Option<usize>,
+
+    /// With --target-successes, give up after this many requests
+    /// [default: 10 times the target]
+    #[arg(long, value_name = "N", requires = "target_successes")]
+    max_attempts: Option<usize>,
+
+    /// Stop dispatching once this many request body bytes have been sent
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    max_bytes_sent: Option<u64>,
+
+    /// Stop dispatching once this many response body bytes have been received
+    #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+    max_bytes_received: Option<u64>,
+
+    /// Number of HTTP/2 connections to spread the requests across
+    #[arg(short, long, default_value_t = 1)]
+    connections: usize,
+
+    /// Open more connections, up to this many, when completions fall behind
+    /// the target rate
+    #[arg(long, value_name = "N", conflicts_with = "replay")]
+    max_connections: Option<usize>,
+
+    /// Maximum number of requests in flight at once
+    #[arg(long)]
+    max_concurrency: Option<usize>,
+
+    /// Interpret --rate as the rate each connection should sustain
+    #[arg(long)]
+    rate_per_connection: bool,
+
+    /// Fail the run if the achieved rate is below this percentage of the target
+    #[arg(long, value_name = "PCT")]
+    require_rate: Option<f64>,
+
+    /// Search for the highest rate whose probe runs stay within --slo-p99,
+    /// starting from --rate
+    #[arg(
+        long,
+        requires = "slo_p99",
+        conflicts_with_all = ["connect_only", "stop_on_first_success", "replay", "rate_schedule", "events"]
+    )]
+    find_max_rate: bool,
+
+    /// With --find-max-rate, the p99 latency in milliseconds a rate must stay
+    /// within to pass
+    #[arg(long, value_name = "MS", requires = "find_max_rate")]
+    slo_p99: Option<u64>,
+
+    /// With --find-max-rate, how long each probe run sends requests for
+    #[arg(long, value_name = "SECS", default_value_t = 5.0)]
+    probe_duration: f64,
+
+    /// Adjust the rate during the run like a congestion-aware client: raise it
+    /// additively while latency stays below --target-latency, halve it when
+    /// latency exceeds it
+    #[arg(
+        long,
+        requires = "target_latency",
+        conflicts_with_all = ["replay", "rate_schedule", "find_max_rate", "connect_only", "stop_on_first_success"]
+    )]
+    adaptive: bool,
+
+    /// With --adaptive, the mean latency in milliseconds to keep the rate under
+    #[arg(long, value_name = "MS", requires = "adaptive")]
+    target_latency: Option<u64>,
+
+    /// Exit immediately if the target is unreachable at startup; set to false
+    /// to retry connecting up to --connect-retries times first
+    #[arg(long, action = clap::ArgAction::Set, default_value_t = true)]
+    abort_on_connection_failure: bool,
+
+    /// Connection attempts to retry at startup when not aborting immediately
+    #[arg(long, default_value_t = 3)]
+    connect_retries: usize,
+
+    /// Run over this already connected socket, e.g. one passed in by a
+    /// supervisor, instead of dialing the address
+    #[arg(
+        long,
+        value_name = "FD",
+        conflicts_with_all = ["connections", "max_connections", "connect_only", "stop_on_first_success"]
+    )]
+    connection_fd: Option<i32>,
+
+    /// Keep each request open and measure the time between streamed body
+    /// chunks (e.g. server-sent events) instead of request completion
+    #[arg(long)]
+    streaming: bool,
+
+    /// With --streaming, close each stream after this many seconds and count
+    /// it as completed rather than failed
+    #[arg(long, value_name = "SECS", requires = "streaming")]
+    max_open_time: Option<f64>,
+
+    /// Cancel requests still unanswered after this many milliseconds,
+    /// resetting their streams, and count them as cancelled rather than failed
+    #[arg(long, value_name = "MS", conflicts_with_all = ["streaming", "synthetic"])]
+    cancel_after: Option<u64>,
+
+    /// Send a few probe requests first and abort if most of them return 4xx
+    #[arg(long)]
+    fail_fast_on_4xx: bool,
+
+    /// Skip the --fail-fast-on-4xx probe requests
+    #[arg(long, overrides_with = "fail_fast_on_4xx")]
+    skip_preflight: bool,
+
+    /// Only establish and tear down connections, without sending requests
+    #[arg(long)]
+    connect_only: bool,
+
+    /// Send a single request, ignoring --rate and --total, and print its
+    /// status, headers and body instead of load test stats
+    #[arg(
+        long,
+        conflicts_with_all = ["connect_only", "stop_on_first_success", "find_max_rate", "coordinator", "worker"]
+    )]
+    once: bool,
+
+    /// Don't contact a server: complete each request after a latency drawn
+    /// from this distribution, e.g. `lognormal:50:1.5` (median 50ms), to
+    /// check the reported stats against known values
+    #[arg(
+        long,
+        value_name = "DIST",
+        conflicts_with_all = ["connection_fd", "max_connections", "fail_fast_on_4xx", "streaming", "connect_only", "once", "stop_on_first_success", "coordinator"]
+    )]
+    synthetic: Option<LatencyDistribution>,
+
+    /// Run the requests twice, over one reused connection and over a new
+    /// connection per request, and report the cost of connection setup
+    #[arg(
+        long,
+        conflicts_with_all = ["connect_only", "once", "synthetic", "stop_on_first_success", "find_max_rate", "coordinator", "worker", "connection_fd"]
+    )]
+    compare_reuse: bool,
+
+    /// Send requests at the target rate until one returns a 2xx status, then
+    /// exit with how long it took; useful for waiting on a server to come up
+    #[arg(long, conflicts_with = "connect_only")]
+    stop_on_first_success: bool,
+
+    /// With --stop-on-first-success, give up and exit non-zero after this
+    /// many seconds [default: wait indefinitely]
+    #[arg(long, value_name = "SECS", requires = "stop_on_first_success")]
+    max_wait: Option<f64>,
+
+    /// Wait for --workers worker processes to connect on this address, split
+    /// --rate and --total between them and print their combined summary
+    #[arg(
+        long,
+        value_name = "ADDR",
+        requires = "workers",
+        conflicts_with_all = ["worker", "connect_only", "stop_on_first_success", "find_max_rate", "target_successes", "replay", "rate_schedule"]
+    )]
+    coordinator: Option<String>,
+
+    /// With --coordinator, the number of workers to wait for
+    #[arg(long, value_name = "N", requires = "coordinator")]
+    workers: Option<usize>,
+
+    /// Take the rate and total of this run from the --coordinator at this
+    /// address and report the results back to it
+    #[arg(
+        long,
+        value_name = "ADDR",
+        conflicts_with_all = ["connect_only", "stop_on_first_success", "find_max_rate", "target_successes", "replay", "rate_schedule"]
+    )]
+    worker: Option<String>,
+
+    /// HTTP method [default: GET, or POST when a body is given]
+    #[arg(short = 'X', long)]
+    method: Option<Method>,
+
+    /// Request header in the format `Name: Value`; may be repeated
+    #[arg(short = 'H', long, value_name = "HEADER")]
+    header: Vec<String>,
+
+    /// File with one `Name: Value` header per line; --header flags override
+    /// headers of the same name
+    #[arg(long, value_name = "FILE")]
+    headers_file: Option<PathBuf>,
+
+    /// File of header sets, `Name: Value` lines separated by blank lines;
+    /// each request sends one set on top of the other headers
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["once", "compare_reuse", "stop_on_first_success", "connect_only"]
+    )]
+    header_set_file: Option<PathBuf>,
+
+    /// Order in which requests pick from the --header-set-file sets
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = HeaderSetOrder::RoundRobin,
+        requires = "header_set_file"
+    )]
+    header_set_order: HeaderSetOrder,
+
+    /// Send the headers of a recent browser's page load (User-Agent, Accept,
+    /// sec-* and so on); --headers-file and --header override them
+    #[arg(long, value_enum, value_name = "BROWSER")]
+    pretend_browser: Option<Browser>,
+
+    /// Send each request with a deadline this many milliseconds after dispatch,
+    /// counting responses reporting it as exceeded as failures
+    #[arg(long, value_name = "MS")]
+    deadline: Option<u64>,
+
+    /// How the --deadline is sent
+    #[arg(long, value_enum, default_value_t = DeadlineFormat::Relative)]
+    deadline_format: DeadlineFormat,
+
+    /// Header carrying relative and absolute --deadline values
+    #[arg(long, value_name = "NAME", default_value = "x-request-deadline")]
+    deadline_header: HeaderName,
+
+    /// Randomize the order of --header headers on every request
+    #[arg(long)]
+    shuffle_headers: bool,
+
+    /// Ask HPACK never to index --header values in its dynamic table
+    #[arg(long)]
+    no_hpack_indexing: bool,
+
+    /// Send a W3C `traceparent` header with a fresh trace ID per request, and
+    /// report the trace IDs of slow and failed requests
+    #[arg(long)]
+    trace_context: bool,
+
+    /// With --trace-context, also send this `tracestate` header value
+    #[arg(long, value_name = "VALUE", requires = "trace_context")]
+    trace_state: Option<HeaderValue>,
+
+    /// Send an RFC 9218 `priority` header from one of these classes, in the
+    /// format `VALUE[@WEIGHT]` (e.g. `u=0,i@3`), picked at random by weight,
+    /// and report latency per class; may be repeated
+    #[arg(long, value_name = "CLASS")]
+    priority: Vec<PriorityClass>,
+
+    /// Seed for the random number generator, making randomized runs repeatable
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Send CONNECT requests, counting a 2xx response (tunnel established) as
+    /// success
+    #[arg(long, conflicts_with_all = ["method", "replay"])]
+    connect_method: bool,
+
+    /// Authority to tunnel to with --connect-method [default: the server address]
+    #[arg(long, value_name = "HOST:PORT", requires = "connect_method")]
+    connect_target: Option<Authority>,
+
+    /// Send extended CONNECT requests with this `:protocol`, e.g. websocket
+    #[arg(
+        long,
+        value_name = "PROTOCOL",
+        requires = "connect_method",
+        conflicts_with = "connect_target"
+    )]
+    connect_protocol: Option<String>,
+
+    /// Send this :authority (and Host, unless set with -H) instead of the
+    /// server address, which is still the one connected to. There are no TLS
+    /// options (SNI, name resolution) for it to interact with
+    #[arg(long, value_name = "HOST[:PORT]")]
+    authority_override: Option<Authority>,
+
+    /// Request body file; repeat to cycle through several bodies round-robin
+    #[arg(long)]
+    body_file: Vec<PathBuf>,
+
+    /// Directory whose files are used as request bodies, cycled round-robin
+    #[arg(long)]
+    body_dir: Option<PathBuf>,
+
+    /// Add a `FIELD=VALUE` field to a multipart/form-data request body; may be
+    /// repeated
+    #[arg(long, value_name = "FIELD=VALUE", conflicts_with_all = ["body_file", "body_dir"])]
+    form: Vec<String>,
+
+    /// Add the file at PATH as field FIELD of a multipart/form-data request
+    /// body; may be repeated
+    #[arg(long, value_name = "FIELD=@PATH", conflicts_with_all = ["body_file", "body_dir"])]
+    form_file: Vec<String>,
+
+    /// Replay a log of `<seconds> <METHOD> <path>` lines at their original timing
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Speed-up factor applied to the replay log timing
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0)]
+    replay_speed: f64,
+
+    /// Read a fourth `<client>` column from the --replay log and send each
+    /// client's requests on a connection of its own, open from its first to
+    /// its last request
+    #[arg(
+        long,
+        requires = "replay",
+        conflicts_with_all = ["connections", "connection_fd"]
+    )]
+    replay_per_client: bool,
+
+    /// Follow a schedule of `<duration>:<rate>` lines instead of --rate/--total
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    rate_schedule: Option<PathBuf>,
+
+    /// With --rate-schedule, open the --connections gradually, in proportion
+    /// to each phase's share of the peak rate, instead of all at the start
+    #[arg(
+        long,
+        requires = "rate_schedule",
+        conflicts_with_all = ["max_connections", "connection_fd", "rate_per_connection"]
+    )]
+    connection_ramp: bool,
+
+    /// Stream request bodies in chunks of this many bytes
+    #[arg(long, value_name = "BYTES")]
+    body_chunk_size: Option<usize>,
+
+    /// Delay between request body chunks in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    body_chunk_delay: u64,
+
+    /// Generate requests from the `build_request` hook of a rhai script
+    #[cfg(feature = "script")]
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+
+    /// Where response time measurement starts and stops
+    #[arg(long, value_enum, default_value_t = LatencyFrom::Body)]
+    latency_from: LatencyFrom,
+
+    /// How latency percentiles are computed
+    #[arg(long, value_enum, default_value_t = PercentileEngine::Exact)]
+    percentile_engine: PercentileEngine,
+
+    /// Highest latency in seconds the hdr percentile engine can track; larger
+    /// samples are clamped to it
+    #[arg(long, value_name = "SECS", default_value_t = 60.0)]
+    max_latency_record: f64,
+
+    /// Significant figures the hdr percentile engine keeps, trading memory
+    /// for precision
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..=5))]
+    sigfigs: u8,
+
+    /// Report bootstrapped confidence intervals at this level for the latency
+    /// percentiles; requires the exact percentile engine
+    #[arg(long, value_name = "PCT")]
+    confidence: Option<f64>,
+
+    /// Boolean expression over `status`, `latency_ms`, `body_size`, `body` and
+    /// `header("name")` that a response must satisfy to count as a success
+    #[arg(long, value_name = "EXPR")]
+    success_expr: Option<String>,
+
+    /// Report latency percentiles per response status code
+    #[arg(long)]
+    latency_by_status: bool,
+
+    /// Also report latency percentiles with each successful request weighted
+    /// by the bytes it transferred; keeps every sample whatever the engine
+    #[arg(long, conflicts_with = "synthetic")]
+    cost_weighted: bool,
+
+    /// Unit latencies are printed in
+    #[arg(long, value_enum, default_value_t = LatencyUnit::Auto)]
+    latency_unit: LatencyUnit,
+
+    /// Digits printed after the decimal point of latencies
+    #[arg(long, value_name = "DIGITS", default_value_t = 2)]
+    precision: usize,
+
+    /// Print the N slowest requests at the end of the run
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    top_slow: usize,
+
+    /// Keep per-request detail (--events, --wire-timing, --top-slow, failed
+    /// request traces) for only this percentage of requests, picked at
+    /// random; aggregate stats still cover every request
+    #[arg(long, value_name = "PCT")]
+    detail_sample_rate: Option<f64>,
+
+    /// Maximum response body size in bytes before a request is aborted
+    #[arg(long, default_value_t = 4 * 1024 * 1024)]
+    max_body_size: usize,
+
+    /// Comma-separated CPU cores to pin the runtime's worker threads to, one
+    /// worker per core
+    #[arg(long, value_name = "CORES", value_delimiter = ',')]
+    cpu_affinity: Vec<usize>,
+
+    /// Write an event for every completed request to stdout, moving the
+    /// summary to stderr
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    events: Option<EventFormat>,
+
+    /// Write when each request was queued, handed to the connection, got its
+    /// response headers and finished its body to FILE, in nanoseconds since
+    /// the start of the run; combine with --detail-sample-rate to sample
+    #[arg(long, value_name = "FILE")]
+    wire_timing: Option<PathBuf>,
+
+    /// Report the memory, file descriptors and CPU time used by this tool
+    #[arg(long)]
+    client_stats: bool,
+
+    /// Fit a trend to the per-minute p99 and error rate and warn about upward
+    /// drift, e.g. from leaks during long runs
+    #[arg(long)]
+    soak_report: bool,
+
+    /// Print heuristic hints about likely bottlenecks (client CPU, stream
+    /// limits, flow control, tail latency)
+    #[arg(long)]
+    hints: bool,
+
+    /// Sample TCP_INFO from the connections and report RTT, retransmits and
+    /// congestion window (Linux only)
+    #[arg(long)]
+    tcp_info: bool,
+
+    /// Probe this URL on a separate connection throughout the run and report
+    /// its health separately
+    #[arg(long, value_name = "URL")]
+    health_url: Option<String>,
+
+    /// Seconds between --health-url probes
+    #[arg(
+        long,
+        value_name = "SECS",
+        default_value_t = 1.0,
+        requires = "health_url"
+    )]
+    health_interval: f64,
+
+    /// Include diagnostic notes in the summary
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Additionally write the final summary to this file
+    #[arg(long)]
+    summary_file: Option<PathBuf>,
+
+    /// Format the final summary is printed and written in
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// POST the summary metrics as InfluxDB line protocol to this write URL
+    /// at the end of the run, e.g. `http://localhost:8086/write?db=loadtest`
+    #[arg(long, value_name = "URL")]
+    influxdb: Option<Uri>,
+
+    /// Measurement name of the --influxdb points
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "http2_load_test",
+        requires = "influxdb"
+    )]
+    influxdb_measurement: String,
+
+    /// Tag the --influxdb points with KEY=VALUE, e.g. `git_sha=1a2b3c`; may be
+    /// repeated
+    #[arg(long = "tag", value_name = "KEY=VALUE", requires = "influxdb")]
+    tags: Vec<Tag>,
+
+    /// Also push the progress of the run to --influxdb every SECS seconds
+    #[arg(long, value_name = "SECS", requires = "influxdb")]
+    influxdb_interval: Option<f64>,
+
+    /// Fail the run when the final --influxdb push fails instead of warning
+    #[arg(long, requires = "influxdb")]
+    influxdb_required: bool,
+
+    /// Sample the tool's own CPU usage during the run and write it to this
+    /// file, as a flamegraph if it ends in `.svg` and a pprof profile otherwise
+    #[cfg(feature = "profile")]
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Whether the request body is assembled from --form and --form-file
+    fn is_multipart(&self) -> bool {
+        !self.form.is_empty() || !self.form_file.is_empty()
+    }
+
+    fn percentiles(&self) -> PercentileConfig {
+        PercentileConfig {
+            engine: self.percentile_engine,
+            hdr_max: Duration::from_secs_f64(self.max_latency_record),
+            hdr_sigfigs: self.sigfigs,
+        }
+    }
+
+    fn latency_format(&self) -> LatencyFormat {
+        LatencyFormat {
+            unit: self.latency_unit,
+            precision: self.precision,
+        }
+    }
+}
+
+/// Exit code used when the target cannot be reached at startup
+const EXIT_UNREACHABLE: u8 = 3;
+
+/// Responses faster than this are at the limit of what the timer can resolve
+const TIMER_FLOOR: Duration = Duration::from_micros(5);
+
+/// How often --max-connections compares the completion rate with the target
+const SCALE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of probe requests sent by --fail-fast-on-4xx
+const PREFLIGHT_REQUESTS: usize = 5;
+
+/// Response body bytes --once prints at most
+const ONCE_BODY_LIMIT: usize = 64 * 1024;
+
+/// Number of failed requests whose --trace-context trace IDs are reported
+const FAILED_TRACES: usize = 10;
+
+/// Delay between startup connection attempts
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How often --adaptive compares the latency with --target-latency
+const ADAPT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// --adaptive raises the rate by this fraction of --rate per interval, which
+/// is also the lowest rate it backs off to
+const ADAPT_STEP: f64 = 0.1;
+
+/// Factor --adaptive multiplies the rate by when latency exceeds the target
+const ADAPT_DECREASE: f64 = 0.5;
+
+/// Upper bound on the probe runs of a --find-max-rate search
+const MAX_RATE_PROBES: usize = 12;
+
+/// --find-max-rate stops once the gap between the highest passing and the
+/// lowest failing rate is within this fraction of the latter
+const RATE_SEARCH_PRECISION: f64 = 0.05;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let output = cli.output;
+    let pinned_cores = Arc::new(Mutex::new(BTreeSet::new()));
+
+    let result = build_runtime(&cli, pinned_cores.clone())
+        .and_then(|runtime| runtime.block_on(try_main(cli, pinned_cores)));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            match output {
+                // Failed checks come after the report, which already holds them
+                OutputFormat::Json if !err.is::<CheckFailed>() => {
+                    let kind = if err.is::<Unreachable>()
+                        || err.chain().any(|cause| cause.is::<hyper::Error>())
+                    {
+                        ErrorKind::Connection
+                    } else {
+                        ErrorKind::Config
+                    };
+                    println!("{}", output::error(kind, &err));
+                }
+                _ => eprintln!("Error: {:?}", err),
+            }
+            if err.is::<Unreachable>() {
+                ExitCode::from(EXIT_UNREACHABLE)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+/// Build the multi-threaded runtime, pinning its worker threads to the
+/// `--cpu-affinity` cores and recording the cores actually used.
+fn build_runtime(
+    cli: &Cli,
+    pinned_cores: Arc<Mutex<BTreeSet<usize>>>,
+) -> Result<Runtime, anyhow::Error> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+
+    if !cli.cpu_affinity.is_empty() {
+        match core_affinity::get_core_ids() {
+            None => {
+                eprintln!("warning: CPU affinity is not supported here, ignoring --cpu-affinity")
+            }
+            Some(available) => {
+                let mut cores = Vec::with_capacity(cli.cpu_affinity.len());
+                for &id in &cli.cpu_affinity {
+                    match available.iter().find(|core| core.id == id) {
+                        Some(core) => cores.push(*core),
+                        None => anyhow::bail!("--cpu-affinity: no CPU core {}", id),
+                    }
+                }
+
+                let next = AtomicUsize::new(0);
+                builder
+                    .worker_threads(cores.len())
+                    .on_thread_start(move || {
+                        // Blocking pool threads are assigned round-robin too
+                        let core = cores[next.fetch_add(1, Ordering::SeqCst) % cores.len()];
+                        if core_affinity::set_for_current(core) {
+                            pinned_cores.lock().unwrap().insert(core.id);
+                        }
+                    });
+            }
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+async fn try_main(
+    cli: Cli,
+    pinned_cores: Arc<Mutex<BTreeSet<usize>>>,
+) -> Result<(), anyhow::Error> {
+    let uri = target_uri(&cli)?;
+    // The histogram needs a range of at least two of its 1ns units
+    if !(cli.max_latency_record.is_finite() && cli.max_latency_record >= 1e-6) {
+        anyhow::bail!("--max-latency-record must be at least 1µs");
+    }
+    if let Some(url) = &cli.influxdb {
+        if url.scheme_str() != Some("http") || url.host().is_none() {
+            anyhow::bail!("--influxdb must be an http:// URL");
+        }
+    }
+    if cli
+        .influxdb_interval
+        .is_some_and(|secs| !secs.is_finite() || secs <= 0.0)
+    {
+        anyhow::bail!("--influxdb-interval must be positive");
+    }
+    #[cfg(feature = "profile")]
+    let profiler = match &cli.profile {
+        Some(_) => Some(profile::start()?),
+        None => None,
+    };
+
+    let mut report = if cli.connect_only {
+        connect_only(&cli, &uri).await?
+    } else if cli.once {
+        once(&cli, &uri).await?
+    } else if cli.compare_reuse {
+        compare_reuse(&cli, &uri).await?
+    } else if cli.stop_on_first_success {
+        wait_for_success(&cli, &uri).await?
+    } else if cli.find_max_rate {
+        find_max_rate(&cli, &uri).await?
+    } else if let Some(listen) = &cli.coordinator {
+        coordinate(&cli, listen).await?
+    } else if let Some(coordinator) = &cli.worker {
+        run_worker(&cli, &uri, coordinator).await?
+    } else {
+        run(&cli, &uri).await?
+    };
+
+    #[cfg(feature = "profile")]
+    if let (Some(profiler), Some(path)) = (&profiler, &cli.profile) {
+        profile::write(profiler, path)?;
+    }
+
+    if !cli.cpu_affinity.is_empty() {
+        let pinned_cores = pinned_cores.lock().unwrap();
+        if pinned_cores.is_empty() {
+            writeln!(
+                report.summary,
+                "cpu affinity: no worker threads were pinned"
+            )?;
+        } else {
+            let cores: Vec<String> = pinned_cores.iter().map(|id| id.to_string()).collect();
+            writeln!(
+                report.summary,
+                "cpu affinity: worker threads pinned to core(s) {}",
+                cores.join(", ")
+            )?;
+        }
+    }
+
+    let printed = match cli.output {
+        OutputFormat::Text => report.summary.clone(),
+        OutputFormat::Json => format!("{}\n", output::json(&report)),
+    };
+    // Keep stdout for the event stream when one is written
+    if cli.events.is_some() {
+        eprint!("{}", printed);
+    } else {
+        print!("{}", printed);
+    }
+    if let Some(path) = &cli.summary_file {
+        write_atomically(path, &printed)?;
+    }
+
+    if let Some(url) = &cli.influxdb {
+        let line = influxdb::summary_line(&cli.influxdb_measurement, &cli.tags, &report);
+        if let Err(err) = influxdb::push(url, line).await {
+            let message = format!("failed to push the results to --influxdb: {:#}", err);
+            if cli.influxdb_required {
+                return Err(CheckFailed(message).into());
+            }
+            eprintln!("warning: {}", message);
+        }
+    }
+
+    if let Some(shortfall) = report.shortfall {
+        return Err(CheckFailed(shortfall).into());
+    }
+
+    if let Some(pct) = cli.require_rate {
+        let required_rate = report.target_rate * pct / 100.0;
+        if report.achieved_rate < required_rate {
+            return Err(CheckFailed(format!(
+                "test invalid (client-limited): achieved {:.2} req/s, below {}% of the {:.2} req/s target",
+                report.achieved_rate,
+                pct,
+                report.target_rate
+            ))
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// The URI given by the address, or assembled from --host and --path.
+fn target_uri(cli: &Cli) -> Result<Uri, anyhow::Error> {
+    match (&cli.address, &cli.host) {
+        (Some(address), _) => parse_address(address),
+        (None, Some(host)) => {
+            let path = cli
+                .path
+                .clone()
+                .unwrap_or_else(|| PathAndQuery::from_static("/"));
+            with_path(&parse_address(host)?, &path)
+        }
+        // Only --synthetic runs go without an address, and they send nothing
+        (None, None) => Ok(Uri::from_static("http://localhost/")),
+    }
+}
+
+/// Parse a server address, defaulting to the http scheme.
+fn parse_address(address: &str) -> Result<Uri, anyhow::Error> {
+    let mut uri = address.parse::<hyper::Uri>()?;
+    if uri.scheme().is_none() {
+        uri = Uri::builder()
+            .scheme("http")
+            .authority(uri.authority().unwrap().as_str())
+            .path_and_query(uri.path_and_query().map(|pq| pq.as_str()).unwrap_or(""))
+            .build()
+            .unwrap();
+    }
+    Ok(uri)
+}
+
+/// Outcome of a run: the human readable summary plus the figures that
+/// pass/fail checks are evaluated against.
+struct Report {
+    summary: String,
+    target_rate: f64,
+    achieved_rate: f64,
+    /// Percentage of requests (or connections) that succeeded
+    success_rate: f64,
+    p99_latency: Duration,
+    /// Response body sizes, for runs that record them
+    response_size: Option<SizeSummary>,
+    /// Structured figures of a load run, for --output json
+    stats: Option<RunStats>,
+    /// Why the run fell short of its stopping condition, failing it after
+    /// the summary is printed
+    shortfall: Option<String>,
+}
+
+/// Run the load test and report the results.
+async fn run(cli: &Cli, uri: &Uri) -> Result<Report, anyhow::Error> {
+    let connections = cli.connections.max(1);
+    let rate = if cli.rate_per_connection {
+        cli.rate * connections as f64
+    } else {
+        cli.rate
+    };
+    let delay = Duration::from_secs_f64(1.0 / rate);
+
+    if cli.target_successes == Some(0) {
+        anyhow::bail!("--target-successes must be at least 1");
+    }
+    if cli.replay_speed <= 0.0 {
+        anyhow::bail!("--replay-speed must be positive");
+    }
+    if cli
+        .max_open_time
+        .is_some_and(|secs| !secs.is_finite() || secs <= 0.0)
+    {
+        anyhow::bail!("--max-open-time must be positive");
+    }
+    if cli.cancel_after == Some(0) {
+        anyhow::bail!("--cancel-after must be at least 1");
+    }
+    if cli
+        .detail_sample_rate
+        .is_some_and(|pct| !(pct > 0.0 && pct <= 100.0))
+    {
+        anyhow::bail!("--detail-sample-rate must be greater than 0 and at most 100");
+    }
+    if let Some(level) = cli.confidence {
+        if !(level > 0.0 && level < 100.0) {
+            anyhow::bail!("--confidence must be between 0 and 100");
+        }
+        if !matches!(cli.percentile_engine, PercentileEngine::Exact) {
+            anyhow::bail!("--confidence requires --percentile-engine exact");
+        }
+    }
+    let replay = match &cli.replay {
+        Some(path) => Some(replay::load(path, cli.replay_per_client)?),
+        None => None,
+    };
+    let clients = match &replay {
+        Some(entries) if cli.replay_per_client => Some(replay::Clients::new(entries)),
+        _ => None,
+    };
+    let schedule = match &cli.rate_schedule {
+        Some(path) => {
+            let mut phases = schedule::load(path)?;
+            if cli.rate_per_connection {
+                for phase in &mut phases {
+                    phase.rate *= connections as f64;
+                }
+            }
+            Some(phases)
+        }
+        None => None,
+    };
+    let (total_requests, target_rate) = match (&replay, &schedule) {
+        (Some(entries), _) => {
+            let span = entries.last().unwrap().offset.as_secs_f64() / cli.replay_speed;
+            // A log of one instant, e.g. a single entry, is sent at once and
+            // has no rate to target
+            if span == 0.0 {
+                if cli.require_rate.is_some() {
+                    anyhow::bail!(
+                        "--require-rate needs a --replay log spanning more than an instant"
+                    );
+                }
+                (entries.len(), f64::NAN)
+            } else {
+                (entries.len(), entries.len() as f64 / span)
+            }
+        }
+        (None, Some(phases)) => {
+            let total: usize = phases.iter().map(|phase| phase.requests()).sum();
+            let span: f64 = phases
+                .iter()
+                .map(|phase| phase.duration.as_secs_f64())
+                .sum();
+            (total, total as f64 / span)
+        }
+        (None, None) => match cli.target_successes {
+            Some(target) => (cli.max_attempts.unwrap_or(target.saturating_mul(10)), rate),
+            None => (cli.total, rate),
+        },
+    };
+
+    let bodies = load_bodies(cli)?;
+    let method = if cli.connect_method {
+        Method::CONNECT
+    } else {
+        cli.method.clone().unwrap_or(if bodies.is_empty() {
+            Method::GET
+        } else {
+            Method::POST
+        })
+    };
+    let base_uri = request_base(cli, uri)?;
+    // A plain CONNECT request only names the authority to tunnel to
+    let request_uri = if cli.connect_method && cli.connect_protocol.is_none() {
+        let target = cli.connect_target.clone();
+        Uri::from(target.unwrap_or_else(|| base_uri.authority().unwrap().clone()))
+    } else {
+        base_uri.clone()
+    };
+
+    let user_headers = user_headers(cli)?;
+    let header_sets = match &cli.header_set_file {
+        Some(path) => headers::load_sets(path)?,
+        None => Vec::new(),
+    };
+    // The other headers under each set, with the set's replacing any of the
+    // same name
+    let set_headers: Vec<_> = header_sets
+        .iter()
+        .map(|set| {
+            let mut merged = user_headers.clone();
+            merged.retain(|(name, _)| !set.headers.iter().any(|(header, _)| header == name));
+            merged.extend(set.headers.iter().cloned());
+            merged
+        })
+        .collect();
+    let mut set_uses = vec![0; header_sets.len()];
+    let mut rng = StdRng::seed_from_u64(cli.seed);
+
+    #[cfg(feature = "script")]
+    let script = match &cli.script {
+        Some(path) => Some(Arc::new(script::Script::load(path)?)),
+        None => None,
+    };
+    let success_expr = match &cli.success_expr {
+        Some(expr) => Some(Arc::new(SuccessExpr::parse(expr)?)),
+        None => None,
+    };
+
+    #[cfg(feature = "script")]
+    let keep_body = success_expr.is_some()
+        || script
+            .as_ref()
+            .is_some_and(|script| script.wants_response());
+    #[cfg(not(feature = "script"))]
+    let keep_body = success_expr.is_some();
+
+    // Shared counters and vars
+    let tally = Arc::new(Tally::new(
+        cli,
+        clients.as_ref().map_or(0, |clients| clients.names.len()),
+        schedule.as_ref().map_or(0, Vec::len),
+    ));
+    let stream_stats = cli.streaming.then(|| {
+        Arc::new(Mutex::new(StreamStats {
+            messages: 0,
+            gaps: Percentiles::new(cli.percentiles()),
+        }))
+    });
+    let drain = DrainOptions {
+        max_body_size: cli.max_body_size,
+        keep_body,
+        stream_stats: stream_stats.clone(),
+        max_open_time: cli.max_open_time.map(Duration::from_secs_f64),
+        cancel_after: cli.cancel_after.map(Duration::from_millis),
+        received: Some(tally.bytes_received.clone()),
+    };
+    let concurrency = Arc::new(Semaphore::new(
+        cli.max_concurrency.unwrap_or(Semaphore::MAX_PERMITS),
+    ));
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let completed = Arc::new(AtomicUsize::new(0));
+    let in_flight_samples = Arc::new(Mutex::new(Vec::new()));
+    let priority_picker = if cli.priority.is_empty() {
+        None
+    } else {
+        Some(WeightedIndex::new(
+            cli.priority.iter().map(|class| class.weight),
+        )?)
+    };
+
+    let (events, event_writer) = match cli.events {
+        Some(EventFormat::Jsonl) => {
+            let (sender, writer) = events::spawn_writer(std::io::stdout());
+            (Some(sender), Some(writer))
+        }
+        None => (None, None),
+    };
+    let (wire, wire_writer) = match &cli.wire_timing {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .map_err(|err| anyhow::anyhow!("failed to create {}: {}", path.display(), err))?;
+            let (sender, writer) = events::spawn_writer(file);
+            (Some(sender), Some(writer))
+        }
+        None => (None, None),
+    };
+
+    let tcp_info = cli.tcp_info.then(|| Arc::new(TcpInfoStats::default()));
+    let mut senders = Vec::with_capacity(connections);
+    if cli.synthetic.is_some() {
+        // Synthetic requests never reach a connection
+    } else if let Some(fd) = cli.connection_fd {
+        let stream = TcpStream::from_std(connection_fd::adopt(fd)?)?;
+        senders.push(handshake(stream, tcp_info.as_deref()).await?);
+    } else {
+        let initial = match &schedule {
+            Some(phases) if cli.connection_ramp => {
+                schedule::ramp_connections(phases, 0, connections)
+            }
+            // Clients open their own connections, this one checks the target
+            // is reachable and stands in for a client whose connection fails
+            _ if clients.is_some() => 1,
+            _ => connections,
+        };
+        for _ in 0..initial {
+            senders.push(connect_at_startup(cli, uri, tcp_info.as_deref()).await?);
+        }
+    }
+
+    let peak_fds: Arc<Mutex<Option<usize>>> = Arc::new(Mutex::new(None));
+
+    if cli.fail_fast_on_4xx && !cli.skip_preflight {
+        let template = RequestTemplate {
+            method: method.clone(),
+            uri: request_uri.clone(),
+            headers: headers::to_map(&user_headers, cli.no_hpack_indexing),
+            bodies: bodies.clone(),
+        };
+        preflight(&mut senders[0], &template, cli).await?;
+    }
+
+    let health = match &cli.health_url {
+        Some(url) => {
+            if !cli.health_interval.is_finite() || cli.health_interval <= 0.0 {
+                anyhow::bail!("--health-interval must be positive");
+            }
+            Some(health::spawn(
+                parse_address(url)?,
+                Duration::from_secs_f64(cli.health_interval),
+                cli.max_body_size,
+                cli.percentiles(),
+            ))
+        }
+        None => None,
+    };
+
+    let in_flight_clone = in_flight.clone();
+    let in_flight_samples_clone = in_flight_samples.clone();
+    let peak_fds_clone = peak_fds.clone();
+    let tcp_info_clone = tcp_info.clone();
+    let client_stats = cli.client_stats;
+
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_millis(100)).await;
+            let sample = in_flight_clone.load(std::sync::atomic::Ordering::SeqCst);
+            in_flight_samples_clone.lock().unwrap().push(sample);
+            if let Some(tcp_info) = &tcp_info_clone {
+                tcp_info.sample();
+            }
+            if client_stats {
+                if let Some(fds) = client_stats::open_fds() {
+                    let mut peak = peak_fds_clone.lock().unwrap();
+                    *peak = Some(peak.map_or(fds, |peak| peak.max(fds)));
+                }
+            }
+        }
+    });
+
+    let influxdb_progress = match (&cli.influxdb, cli.influxdb_interval) {
+        (Some(url), Some(secs)) => {
+            let url = url.clone();
+            let measurement = format!("{}_interval", cli.influxdb_measurement);
+            let tags = cli.tags.clone();
+            let completed = completed.clone();
+            let tally = tally.clone();
+            let in_flight = in_flight.clone();
+            Some(tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(Duration::from_secs_f64(secs));
+                // The first tick completes right away
+                ticker.tick().await;
+                let mut last = (Instant::now(), 0, 0);
+                loop {
+                    ticker.tick().await;
+                    let (then, completed_then, successes_then) = last;
+                    let now = Instant::now();
+                    let completed_now = completed.load(Ordering::SeqCst);
+                    let successes_now = *tally.success_count.lock().unwrap();
+                    let requests = (completed_now - completed_then) as f64;
+                    let line = influxdb::line(
+                        &measurement,
+                        &tags,
+                        &[
+                            ("completed", requests),
+                            ("successes", (successes_now - successes_then) as f64),
+                            ("in_flight", in_flight.load(Ordering::SeqCst) as f64),
+                            ("rate", requests / (now - then).as_secs_f64()),
+                        ],
+                    );
+                    if let Err(err) = influxdb::push(&url, line).await {
+                        eprintln!("warning: failed to push progress to --influxdb: {:#}", err);
+                    }
+                    last = (now, completed_now, successes_now);
+                }
+            }))
+        }
+        _ => None,
+    };
+
+    // Perform the requests
+    let dispatch_start = Instant::now();
+    let soak = cli.soak_report.then(|| {
+        Arc::new(Mutex::new(SoakStats::new(
+            dispatch_start,
+            cli.percentiles(),
+        )))
+    });
+    let dispatch_elapsed;
+    let mut phase_starts = Vec::new();
+    let mut phase_connections = Vec::new();
+    let mut dispatched = total_requests;
+    let mut bytes_sent: u64 = 0;
+    let mut budget_reached = None;
+    let mut target_reached = false;
+    let mut trajectory = Vec::new();
+    // The open connection of each --replay-per-client client
+    let mut client_senders = vec![None; clients.as_ref().map_or(0, |c| c.names.len())];
+    let mut connected_clients = 0;
+    let mut peak_clients = 0;
+    {
+        let mut futures = FuturesUnordered::new();
+        let mut delay = delay;
+        // Tokens pace every request after the first, which goes out right away
+        let pacer = TokenBucket::new(1.0 / delay.as_secs_f64());
+        let mut phase_end = 0;
+        let mut last_scale_check = (Instant::now(), 0, 0);
+        let mut last_adapt = Instant::now();
+
+        for i in 0..total_requests {
+            if cli.max_bytes_sent.is_some_and(|max| bytes_sent >= max) {
+                budget_reached = Some("--max-bytes-sent");
+            } else if cli
+                .max_bytes_received
+                .is_some_and(|max| tally.bytes_received.load(Ordering::SeqCst) as u64 >= max)
+            {
+                budget_reached = Some("--max-bytes-received");
+            }
+            // Requests still in flight may push the count past the target
+            target_reached = cli
+                .target_successes
+                .is_some_and(|target| *tally.success_count.lock().unwrap() >= target);
+            if budget_reached.is_some() || target_reached {
+                dispatched = i;
+                break;
+            }
+            if let Some(phases) = &schedule {
+                if i == phase_end {
+                    // Move on to the next phase of the schedule
+                    let phase = &phases[phase_starts.len()];
+                    phase_end += phase.requests();
+                    delay = Duration::from_secs_f64(1.0 / phase.rate);
+                    pacer.set_rate(phase.rate);
+                    if cli.connection_ramp {
+                        let wanted =
+                            schedule::ramp_connections(phases, phase_starts.len(), connections);
+                        while senders.len() < wanted {
+                            match connect(uri, tcp_info.as_deref()).await {
+                                Ok(sender) => senders.push(sender),
+                                Err(err) => {
+                                    eprintln!(
+                                        "warning: failed to open another connection: {}",
+                                        err
+                                    );
+                                    break;
+                                }
+                            }
+                        }
+                        phase_connections.push(senders.len());
+                    }
+                    phase_starts.push(Instant::now());
+                }
+            }
+            if let Some(max) = cli.max_connections {
+                let (checked_at, completed_then, in_flight_then) = last_scale_check;
+                if checked_at.elapsed() >= SCALE_INTERVAL {
+                    let completed_now = completed.load(Ordering::SeqCst);
+                    let in_flight_now = in_flight.load(Ordering::SeqCst);
+                    let completion_rate = (completed_now - completed_then) as f64
+                        / checked_at.elapsed().as_secs_f64();
+                    // Completions lagging the pacer while the backlog keeps growing
+                    // means the open connections are saturated
+                    if completion_rate < 0.9 / delay.as_secs_f64()
+                        && in_flight_now > in_flight_then
+                        && senders.len() < max
+                    {
+                        match connect(uri, tcp_info.as_deref()).await {
+                            Ok(sender) => senders.push(sender),
+                            Err(err) => {
+                                eprintln!("warning: failed to open another connection: {}", err)
+                            }
+                        }
+                    }
+                    last_scale_check = (Instant::now(), completed_now, in_flight_now);
+                }
+            }
+            if let (Some(window), Some(ms)) = (&tally.adapt_window, cli.target_latency) {
+                if last_adapt.elapsed() >= ADAPT_INTERVAL {
+                    let (sum, count) = std::mem::take(&mut *window.lock().unwrap());
+                    // Without completions there is no signal to act on
+                    if count > 0 {
+                        let mean = sum / count;
+                        let step = rate * ADAPT_STEP;
+                        let current = 1.0 / delay.as_secs_f64();
+                        let next = if mean > Duration::from_millis(ms) {
+                            (current * ADAPT_DECREASE).max(step)
+                        } else {
+                            current + step
+                        };
+                        delay = Duration::from_secs_f64(1.0 / next);
+                        pacer.set_rate(next);
+                        trajectory.push((dispatch_start.elapsed(), mean, next));
+                    }
+                    last_adapt = Instant::now();
+                }
+            }
+            let phase = schedule.is_some().then(|| phase_starts.len() - 1);
+            // Spread the requests round-robin across the connections
+            let mut sender = (!senders.is_empty()).then(|| senders[i % senders.len()].clone());
+            let (method, uri) = match &replay {
+                Some(entries) => {
+                    let entry = &entries[i];
+                    let offset = entry.offset.div_f64(cli.replay_speed);
+                    sleep_until((dispatch_start + offset).into()).await;
+                    if let (Some(clients), Some(shared)) = (&clients, &mut sender) {
+                        let client = clients.of_entry[i];
+                        // A client connects with its first request, so it
+                        // appears at its original time
+                        if client_senders[client].is_none() {
+                            match connect(uri, tcp_info.as_deref()).await {
+                                Ok(own) => {
+                                    client_senders[client] = Some(own);
+                                    connected_clients += 1;
+                                    peak_clients = peak_clients.max(connected_clients);
+                                }
+                                Err(err) => eprintln!(
+                                    "warning: failed to open a connection for client {}: {}",
+                                    clients.names[client], err
+                                ),
+                            }
+                        }
+                        if let Some(own) = &client_senders[client] {
+                            *shared = own.clone();
+                        }
+                        // Dropping the handle closes the connection once the
+                        // client's last requests complete
+                        if clients.last_entry[client] == i
+                            && client_senders[client].take().is_some()
+                        {
+                            connected_clients -= 1;
+                        }
+                    }
+                    (entry.method.clone(), with_path(&base_uri, &entry.path)?)
+                }
+                None => (method.clone(), request_uri.clone()),
+            };
+            let enqueued = Instant::now();
+            let tally = tally.clone();
+            let drain = drain.clone();
+            let in_flight = in_flight.clone();
+            let completed = completed.clone();
+            let client = clients.as_ref().map(|clients| clients.of_entry[i]);
+            let events = events.clone();
+            let soak = soak.clone();
+            let wire = wire.clone();
+            let latency_from = cli.latency_from;
+            let request_headers = if set_headers.is_empty() {
+                &user_headers
+            } else {
+                let set = match cli.header_set_order {
+                    HeaderSetOrder::RoundRobin => i % set_headers.len(),
+                    HeaderSetOrder::Random => rng.gen_range(0..set_headers.len()),
+                };
+                set_uses[set] += 1;
+                &set_headers[set]
+            };
+            let mut headers = if cli.shuffle_headers {
+                let mut shuffled = request_headers.clone();
+                shuffled.shuffle(&mut rng);
+                headers::to_map(&shuffled, cli.no_hpack_indexing)
+            } else {
+                headers::to_map(request_headers, cli.no_hpack_indexing)
+            };
+            if let Some(ms) = cli.deadline {
+                let (name, value) = deadline::header(
+                    cli.deadline_format,
+                    &cli.deadline_header,
+                    Duration::from_millis(ms),
+                )?;
+                headers.insert(name, value);
+            }
+            let synthetic_latency = cli.synthetic.map(|dist| dist.sample(&mut rng));
+            let detailed = cli
+                .detail_sample_rate
+                .is_none_or(|pct| rng.gen_bool(pct / 100.0));
+            let trace_id = if cli.trace_context {
+                let (trace_id, traceparent) = trace_context::traceparent(&mut rng);
+                headers.insert(trace_context::TRACEPARENT, traceparent);
+                if let Some(state) = &cli.trace_state {
+                    headers.insert(trace_context::TRACESTATE, state.clone());
+                }
+                Some(trace_id)
+            } else {
+                None
+            };
+            let priority = priority_picker.as_ref().map(|picker| {
+                let class = picker.sample(&mut rng);
+                headers.insert(priority::PRIORITY, cli.priority[class].value.clone());
+                class
+            });
+            let body = if bodies.is_empty() {
+                Bytes::new()
+            } else {
+                bodies[i % bodies.len()].clone()
+            };
+
+            let success_expr = success_expr.clone();
+            #[cfg(feature = "script")]
+            let script = script.clone();
+            #[cfg(feature = "script")]
+            let parts = match &script {
+                Some(script) => script.build_request(i, method, uri, headers, body),
+                None => Ok((method, uri, headers, body)),
+            };
+            #[cfg(not(feature = "script"))]
+            let parts: Result<_, anyhow::Error> = Ok((method, uri, headers, body));
+
+            // A script that fails to build a request fails only that request,
+            // which is still dispatched to be counted
+            let mut request_bytes = 0;
+            let req = match parts {
+                Ok((method, uri, headers, body)) => {
+                    bytes_sent += body.len() as u64;
+                    request_bytes = body.len();
+                    let body = RequestBody::chunked(
+                        body,
+                        cli.body_chunk_size.unwrap_or(0),
+                        Duration::from_millis(cli.body_chunk_delay),
+                    );
+                    let mut req = build_request(method, uri, headers, body)?;
+                    if let Some(protocol) = &cli.connect_protocol {
+                        req.extensions_mut()
+                            .insert(Protocol::from(protocol.as_str()));
+                    }
+                    Ok(req)
+                }
+                Err(err) => Err(err),
+            };
+            let connect_method = cli.connect_method;
+            let request_deadline = cli.deadline.map(Duration::from_millis);
+            let request = RequestInfo {
+                index: i,
+                body_size: request_bytes,
+                priority,
+                client,
+                phase,
+                detailed,
+                trace_id,
+            };
+
+            // Wait for a free slot, holding back dispatch while at the cap
+            let permit = concurrency.clone().acquire_owned().await.unwrap();
+
+            futures.push(tokio::spawn(async move {
+                let _permit = permit;
+                let result = match (req, synthetic_latency, &mut sender) {
+                    (Err(err), _, _) => Err(err),
+                    (Ok(_), Some(latency), _) => {
+                        Ok(synthetic::request(latency, in_flight, enqueued).await)
+                    }
+                    (Ok(req), None, Some(sender)) => {
+                        make_request(sender, req, in_flight, drain, enqueued, latency_from).await
+                    }
+                    (Ok(_), None, None) => unreachable!("only synthetic runs have no connections"),
+                };
+                #[cfg(feature = "script")]
+                let result = result.and_then(|sample| {
+                    if let Some(script) = &script {
+                        let body = sample.body.as_deref().unwrap_or_default();
+                        script.on_response(sample.status, &sample.headers, body)?;
+                    }
+                    Ok(sample)
+                });
+                let result = result.and_then(|sample| {
+                    if connect_method && !sample.status.is_success() {
+                        return Err(TunnelRefused {
+                            status: sample.status,
+                        }
+                        .into());
+                    }
+                    if let Some(request_deadline) = request_deadline {
+                        // The deadline runs from dispatch, and the response
+                        // headers carry the timeout signal
+                        let waited = sample.timeline.headers - sample.timeline.enqueued;
+                        if deadline::exceeded(
+                            sample.status,
+                            &sample.headers,
+                            waited,
+                            request_deadline,
+                        ) {
+                            return Err(DeadlineExceeded.into());
+                        }
+                    }
+                    Ok(sample)
+                });
+                let result = result.and_then(|sample| {
+                    if let Some(expr) = &success_expr {
+                        let body = sample.body.as_deref().unwrap_or_default();
+                        if !expr.evaluate(sample.status, sample.duration, &sample.headers, body)? {
+                            return Err(success::Rejected.into());
+                        }
+                    }
+                    Ok(sample)
+                });
+
+                match result {
+                    Ok(sample) => {
+                        tally.success(&request, &sample);
+                        if let Some(soak) = &soak {
+                            soak.lock().unwrap().record(Some(sample.duration));
+                        }
+                        if let Some(wire) = wire.as_ref().filter(|_| detailed) {
+                            let _ = wire.send(wire_timing::success(
+                                i,
+                                dispatch_start,
+                                &sample.timeline,
+                                sample.status,
+                            ));
+                        }
+                        if let Some(events) = events.as_ref().filter(|_| detailed) {
+                            let _ = events.send(events::success(
+                                i,
+                                sample.duration,
+                                sample.status,
+                                trace_id,
+                            ));
+                        }
+                    }
+                    Err(err) => {
+                        let category = tally.failure(&request, &err);
+                        if let Some(soak) = &soak {
+                            soak.lock().unwrap().record(None);
+                        }
+                        if let Some(wire) = wire.as_ref().filter(|_| detailed) {
+                            let _ = wire.send(wire_timing::failure(
+                                i,
+                                dispatch_start,
+                                enqueued,
+                                category,
+                            ));
+                        }
+                        if let Some(events) = events.as_ref().filter(|_| detailed) {
+                            let _ = events.send(events::failure(i, category, trace_id));
+                        }
+                    }
+                }
+                completed.fetch_add(1, Ordering::SeqCst);
+            }));
+
+            // Replayed requests are paced by their own timestamps
+            if replay.is_none() {
+                pacer.acquire().await;
+            }
+        }
+        dispatch_elapsed = dispatch_start.elapsed();
+
+        while (futures.next().await).is_some() {}
+    }
+    let elapsed = dispatch_start.elapsed();
+    if let Some(task) = influxdb_progress {
+        task.abort();
+    }
+    let health = health.map(|(stats, task)| {
+        task.abort();
+        stats
+    });
+
+    // Let the writer drain the remaining events before the summary is printed
+    drop(events);
+    if let Some(writer) = event_writer {
+        writer.await?;
+    }
+    drop(wire);
+    if let Some(writer) = wire_writer {
+        writer.await?;
+    }
+
+    let peak_fds = *peak_fds.lock().unwrap();
+    let in_flight_samples = in_flight_samples.lock().unwrap();
+    report::render(
+        cli,
+        &tally,
+        report::Run {
+            target_rate,
+            total_requests,
+            dispatched,
+            completed: completed.load(Ordering::SeqCst),
+            connections,
+            opened: senders.len(),
+            dispatch_start,
+            dispatch_elapsed,
+            elapsed,
+            bytes_sent,
+            budget_reached,
+            target_reached,
+            in_flight_samples: &in_flight_samples,
+            peak_fds,
+            bodies: &bodies,
+            header_sets: &header_sets,
+            set_uses: &set_uses,
+            clients: clients.as_ref(),
+            peak_clients,
+            schedule: schedule.as_deref(),
+            phase_starts: &phase_starts,
+            phase_connections: &phase_connections,
+            trajectory: &trajectory,
+            stream_stats: stream_stats.as_deref(),
+            soak: soak.as_deref(),
+            health: health.as_deref(),
+            tcp_info: tcp_info.as_deref(),
+        },
+        &mut rng,
+    )
+}
+
+/// The URI requests are sent to: the server address, with its authority
+/// replaced by --authority-override.
+fn request_base(cli: &Cli, uri: &Uri) -> Result<Uri, anyhow::Error> {
+    let Some(authority) = &cli.authority_override else {
+        return Ok(uri.clone());
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.authority = Some(authority.clone());
+    Ok(Uri::from_parts(parts)?)
+}
 
-    /// Target request rate (requests per second)
-    #[arg(short, long, default_value_t = 1.0)]
-    rate: f64,
+/// Return `uri` with its path and query replaced by `path`.
+fn with_path(uri: &Uri, path: &PathAndQuery) -> Result<Uri, anyhow::Error> {
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path.clone());
+    Ok(Uri::from_parts(parts)?)
+}
 
-    /// Total number of requests to execute
-    #[arg(short, long, default_value_t = 1)]
-    total: usize,
+/// Headers from --pretend-browser, --headers-file and -H, where later sources
+/// replace earlier entries of the same name.
+fn user_headers(cli: &Cli) -> Result<Vec<(HeaderName, HeaderValue)>, anyhow::Error> {
+    let mut user_headers = cli
+        .pretend_browser
+        .map(Browser::headers)
+        .unwrap_or_default();
+    if let Some(path) = &cli.headers_file {
+        let file_headers = headers::load(path)?;
+        user_headers.retain(|(name, _)| !file_headers.iter().any(|(file, _)| file == name));
+        user_headers.extend(file_headers);
+    }
+    let flag_headers = cli
+        .header
+        .iter()
+        .map(|header| headers::parse(header))
+        .collect::<Result<Vec<_>, _>>()?;
+    user_headers.retain(|(name, _)| !flag_headers.iter().any(|(flag, _)| flag == name));
+    user_headers.extend(flag_headers);
+    if cli.is_multipart() {
+        user_headers.retain(|(name, _)| name != CONTENT_TYPE);
+        let boundary = multipart::boundary(cli.seed);
+        user_headers.push((CONTENT_TYPE, multipart::content_type(&boundary)?));
+    }
+    Ok(user_headers)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    let cli = Cli::parse();
+/// Load the request bodies given by `--body-file` and `--body-dir`, in order,
+/// or the multipart body built from `--form` and `--form-file`.
+fn load_bodies(cli: &Cli) -> Result<Vec<Bytes>, anyhow::Error> {
+    if cli.is_multipart() {
+        let boundary = multipart::boundary(cli.seed);
+        return Ok(vec![multipart::build(
+            &cli.form,
+            &cli.form_file,
+            &boundary,
+        )?]);
+    }
 
-    let mut uri = cli.address.parse::<hyper::Uri>()?;
-    if uri.scheme().is_none() {
-        uri = Uri::builder()
-            .scheme("http")
-            .authority(uri.authority().unwrap().as_str())
-            .path_and_query(uri.path_and_query().map(|pq| pq.as_str()).unwrap_or(""))
-            .build()
-            .unwrap();
+    let mut paths = cli.body_file.clone();
+
+    if let Some(dir) = &cli.body_dir {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                entries.push(path);
+            }
+        }
+        if entries.is_empty() {
+            anyhow::bail!("body directory {} contains no files", dir.display());
+        }
+        entries.sort();
+        paths.extend(entries);
     }
-    let uri = uri;
 
-    let delay = Duration::from_secs_f64(1.0 / cli.rate);
-    let total_requests = cli.total;
+    paths
+        .iter()
+        .map(|path| {
+            fs::read(path)
+                .map(Bytes::from)
+                .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))
+        })
+        .collect()
+}
+
+/// Send `PREFLIGHT_REQUESTS` requests built like the first ones of the run and
+/// fail, describing the responses, if more than half of them return 4xx.
+async fn preflight(
+    sender: &mut SendRequest<RequestBody>,
+    template: &RequestTemplate,
+    cli: &Cli,
+) -> Result<(), anyhow::Error> {
+    let drain = DrainOptions {
+        max_open_time: cli.max_open_time.map(Duration::from_secs_f64),
+        ..DrainOptions::whole(cli.max_body_size, true)
+    };
 
-    // Shared counters and vars
-    let success_count: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
-    let response_times = Arc::new(Mutex::new(Vec::new()));
-    let in_flight = Arc::new(AtomicUsize::new(0));
-    let in_flight_samples = Arc::new(Mutex::new(Vec::new()));
+    let mut details = String::new();
+    let mut client_errors = 0;
+    for i in 0..PREFLIGHT_REQUESTS {
+        let sample = make_request(
+            sender,
+            template.build(i)?,
+            Arc::new(AtomicUsize::new(0)),
+            drain.clone(),
+            Instant::now(),
+            cli.latency_from,
+        )
+        .await?;
+
+        if sample.status.is_client_error() {
+            client_errors += 1;
+            let body = sample.body.unwrap_or_default();
+            let excerpt = String::from_utf8_lossy(&body[..body.len().min(200)]).into_owned();
+            writeln!(details, "  #{}: {}", i, sample.status)?;
+            for (name, value) in &sample.headers {
+                writeln!(
+                    details,
+                    "    {}: {}",
+                    name,
+                    String::from_utf8_lossy(value.as_bytes())
+                )?;
+            }
+            writeln!(details, "    body: {:?}", excerpt)?;
+        }
+    }
+
+    if client_errors * 2 > PREFLIGHT_REQUESTS {
+        anyhow::bail!(
+            "preflight failed: {} of {} probe requests returned 4xx, check the request \
+             (use --skip-preflight to run anyway):\n{}",
+            client_errors,
+            PREFLIGHT_REQUESTS,
+            details.trim_end()
+        );
+    }
+
+    Ok(())
+}
 
+/// Open a TCP connection to the host of `uri` and perform the HTTP/2 handshake.
+/// With `tcp_info`, the socket is also registered for `--tcp-info` sampling.
+async fn connect(
+    uri: &Uri,
+    tcp_info: Option<&TcpInfoStats>,
+) -> Result<SendRequest<RequestBody>, anyhow::Error> {
     // Get the host and the port
     let host = uri.host().expect("uri has no host");
     let port = uri.port_u16().unwrap_or(80);
@@ -57,111 +1775,1120 @@ async fn main() -> Result<(), anyhow::Error> {
 
     // Open a TCP connection to the remote host
     let stream = TcpStream::connect(address).await?;
-    let io = TokioIo::new(stream);
+    handshake(stream, tcp_info).await
+}
+
+/// Perform the HTTP/2 handshake over an open TCP connection.
+async fn handshake(
+    stream: TcpStream,
+    tcp_info: Option<&TcpInfoStats>,
+) -> Result<SendRequest<RequestBody>, anyhow::Error> {
+    let io = Watched::new(stream, tcp_info);
 
     // Create the Hyper client
-    let (sender, conn) = hyper::client::conn::http2::handshake(TokioExecutor::new(), io).await?;
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), io).await?;
 
     // Spawn a task to poll the connection, driving the HTTP state
     tokio::task::spawn(async move {
         if let Err(err) = conn.await {
-            println!("Connection failed: {:?}", err);
+            eprintln!("Connection failed: {:?}", err);
         }
     });
 
-    let in_flight_clone = in_flight.clone();
-    let in_flight_samples_clone = in_flight_samples.clone();
+    // Wait for the server's settings before handing out the connection
+    sender.ready().await?;
 
-    tokio::spawn(async move {
-        loop {
-            sleep(Duration::from_millis(100)).await;
-            let sample = in_flight_clone.load(std::sync::atomic::Ordering::SeqCst);
-            in_flight_samples_clone.lock().unwrap().push(sample);
+    Ok(sender)
+}
+
+/// Returned when the target cannot be reached before the run starts.
+#[derive(Debug)]
+struct Unreachable {
+    attempts: usize,
+    source: anyhow::Error,
+}
+
+impl fmt::Display for Unreachable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "target unreachable at startup after {} attempt(s): {}",
+            self.attempts, self.source
+        )
+    }
+}
+
+impl std::error::Error for Unreachable {}
+
+/// Returned when a run completed but failed a check on its report.
+#[derive(Debug)]
+struct CheckFailed(String);
+
+impl fmt::Display for CheckFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for CheckFailed {}
+
+/// Connect to the target before the run starts, retrying according to
+/// `--abort-on-connection-failure` and `--connect-retries`.
+async fn connect_at_startup(
+    cli: &Cli,
+    uri: &Uri,
+    tcp_info: Option<&TcpInfoStats>,
+) -> Result<SendRequest<RequestBody>, anyhow::Error> {
+    let retries = if cli.abort_on_connection_failure {
+        0
+    } else {
+        cli.connect_retries
+    };
+
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match connect(uri, tcp_info).await {
+            Ok(sender) => return Ok(sender),
+            Err(err) if attempts > retries => {
+                return Err(Unreachable {
+                    attempts,
+                    source: err,
+                }
+                .into())
+            }
+            Err(err) => {
+                eprintln!("connection attempt {} failed: {}", attempts, err);
+                sleep(CONNECT_RETRY_DELAY).await;
+            }
         }
-    });
+    }
+}
 
-    // Perform the requests
+/// Repeatedly establish and drop connections at the target rate, reporting
+/// handshake latency instead of request latency.
+async fn connect_only(cli: &Cli, uri: &Uri) -> Result<Report, anyhow::Error> {
+    let pacer = TokenBucket::new(cli.rate);
+    let total = cli.total;
+
+    let success_count: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
+    let handshake_times = Arc::new(Mutex::new(Percentiles::new(cli.percentiles())));
+
+    let dispatch_start = Instant::now();
+    let dispatch_elapsed;
     {
         let mut futures = FuturesUnordered::new();
 
-        for _ in 0..total_requests {
-            let mut sender = sender.clone();
+        for _ in 0..total {
             let uri = uri.clone();
             let success_count = success_count.clone();
-            let response_times = response_times.clone();
-            let in_flight = in_flight.clone();
-
-            // The authority of our URL will be the hostname of the remote
-            let authority = uri.authority().unwrap().clone();
+            let handshake_times = handshake_times.clone();
 
             futures.push(tokio::spawn(async move {
-                if let Ok(duration) =
-                    make_request(&mut sender, uri, authority.as_str(), in_flight).await
-                {
+                let start = Instant::now();
+                // Dropping the sender closes the connection again
+                if connect(&uri, None).await.is_ok() {
+                    let duration = start.elapsed();
                     {
                         let mut sc = success_count.lock().unwrap();
                         *sc += 1;
                     }
-                    let mut rt = response_times.lock().unwrap();
-                    rt.push(duration);
+                    let mut ht = handshake_times.lock().unwrap();
+                    ht.record(duration);
                 }
             }));
 
-            sleep(delay).await;
+            pacer.acquire().await;
         }
+        dispatch_elapsed = dispatch_start.elapsed();
 
         while (futures.next().await).is_some() {}
     }
 
-    // Gather and compute stats
     let success_count = *success_count.lock().unwrap();
-    let response_times = response_times.lock().unwrap();
-    let in_flight_samples = in_flight_samples.lock().unwrap();
+    let mut handshake_times = handshake_times.lock().unwrap();
 
-    let success_rate = (success_count as f64 / total_requests as f64) * 100.0;
-    let median_response_time = {
-        let mut times = response_times.clone();
-        times.sort();
-        if times.is_empty() {
-            Duration::new(0, 0)
-        } else {
-            times[times.len() / 2]
+    let success_rate = (success_count as f64 / total as f64) * 100.0;
+    let achieved_rate = total as f64 / dispatch_elapsed.as_secs_f64();
+
+    let lf = cli.latency_format();
+    let mut summary = String::new();
+    writeln!(summary, "connection success: {:.1}%", success_rate)?;
+    writeln!(
+        summary,
+        "median handshake time: {}",
+        lf.format(handshake_times.percentile(0.5))
+    )?;
+    writeln!(
+        summary,
+        "p90 handshake time: {}",
+        lf.format(handshake_times.percentile(0.9))
+    )?;
+    writeln!(
+        summary,
+        "p99 handshake time: {}",
+        lf.format(handshake_times.percentile(0.99))
+    )?;
+    writeln!(summary, "achieved rate: {:.2} conn/s", achieved_rate)?;
+    if cli.verbose || !matches!(cli.percentile_engine, PercentileEngine::Exact) {
+        writeln!(
+            summary,
+            "percentile engine: {}",
+            cli.percentile_engine.name()
+        )?;
+    }
+
+    Ok(Report {
+        summary,
+        target_rate: cli.rate,
+        achieved_rate,
+        success_rate,
+        p99_latency: handshake_times.percentile(0.99),
+        response_size: None,
+        stats: None,
+        shortfall: None,
+    })
+}
+
+/// Results of one --compare-reuse leg
+struct ReuseLeg {
+    successes: usize,
+    /// Latency from just before the connection is picked or opened until the
+    /// response body has been read
+    latencies: Percentiles,
+    /// Connect and handshake times, only measured for new connections
+    handshakes: Percentiles,
+    achieved_rate: f64,
+}
+
+/// Send the same requests over one reused connection, then over a new
+/// connection each, and report the difference as the setup cost.
+async fn compare_reuse(cli: &Cli, uri: &Uri) -> Result<Report, anyhow::Error> {
+    let mut reused = reuse_leg(cli, uri, false).await?;
+    let mut fresh = reuse_leg(cli, uri, true).await?;
+
+    let lf = cli.latency_format();
+    let mut delta = |p| {
+        let (fresh, reused) = (
+            fresh.latencies.percentile(p),
+            reused.latencies.percentile(p),
+        );
+        match fresh.checked_sub(reused) {
+            Some(overhead) => format!("+{}", lf.format(overhead)),
+            None => format!("-{}", lf.format(reused - fresh)),
+        }
+    };
+    let (median, p99) = (delta(0.5), delta(0.99));
+    let mut summary = String::new();
+    writeln!(
+        summary,
+        "per-request connection overhead: {} median, {} p99",
+        median, p99
+    )?;
+    writeln!(
+        summary,
+        "connection reuse comparison ({} requests per leg):",
+        cli.total
+    )?;
+    for (name, leg) in [
+        ("reused connection", &mut reused),
+        ("new connections", &mut fresh),
+    ] {
+        writeln!(
+            summary,
+            "  {}: success {:.1}%, median {}, p99 {}, achieved {:.2} req/s",
+            name,
+            leg.successes as f64 / cli.total as f64 * 100.0,
+            lf.format(leg.latencies.percentile(0.5)),
+            lf.format(leg.latencies.percentile(0.99)),
+            leg.achieved_rate
+        )?;
+    }
+    writeln!(
+        summary,
+        "  handshake (TCP connect + HTTP/2): median {}, p99 {}",
+        lf.format(fresh.handshakes.percentile(0.5)),
+        lf.format(fresh.handshakes.percentile(0.99))
+    )?;
+
+    Ok(Report {
+        summary,
+        target_rate: cli.rate,
+        achieved_rate: fresh.achieved_rate,
+        success_rate: fresh.successes as f64 / cli.total as f64 * 100.0,
+        p99_latency: fresh.latencies.percentile(0.99),
+        response_size: None,
+        stats: None,
+        shortfall: None,
+    })
+}
+
+/// Send --total requests at --rate, either all over one connection or each
+/// over a new one that is closed again afterwards.
+async fn reuse_leg(cli: &Cli, uri: &Uri, fresh: bool) -> Result<ReuseLeg, anyhow::Error> {
+    let template = RequestTemplate::new(cli, uri)?;
+    let drain = DrainOptions::whole(cli.max_body_size, false);
+    let shared = if fresh {
+        None
+    } else {
+        Some(connect_at_startup(cli, uri, None).await?)
+    };
+
+    let success_count: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
+    let latencies = Arc::new(Mutex::new(Percentiles::new(cli.percentiles())));
+    let handshakes = Arc::new(Mutex::new(Percentiles::new(cli.percentiles())));
+    let pacer = TokenBucket::new(cli.rate);
+
+    let dispatch_start = Instant::now();
+    let dispatch_elapsed;
+    {
+        let mut futures = FuturesUnordered::new();
+
+        for i in 0..cli.total {
+            let req = template.build(i)?;
+            let uri = uri.clone();
+            let shared = shared.clone();
+            let drain = drain.clone();
+            let success_count = success_count.clone();
+            let latencies = latencies.clone();
+            let handshakes = handshakes.clone();
+
+            futures.push(tokio::spawn(async move {
+                let start = Instant::now();
+                let mut sender = match shared {
+                    Some(sender) => sender,
+                    None => match connect(&uri, None).await {
+                        Ok(sender) => {
+                            handshakes.lock().unwrap().record(start.elapsed());
+                            sender
+                        }
+                        Err(_) => return,
+                    },
+                };
+                let in_flight = Arc::new(AtomicUsize::new(0));
+                // Measured from `start`, so new connections include their setup
+                let result = make_request(
+                    &mut sender,
+                    req,
+                    in_flight,
+                    drain,
+                    start,
+                    LatencyFrom::Enqueue,
+                );
+                if let Ok(sample) = result.await {
+                    if sample.status.is_success() {
+                        *success_count.lock().unwrap() += 1;
+                        latencies.lock().unwrap().record(sample.duration);
+                    }
+                }
+            }));
+
+            pacer.acquire().await;
+        }
+        dispatch_elapsed = dispatch_start.elapsed();
+
+        while (futures.next().await).is_some() {}
+    }
+
+    let successes = *success_count.lock().unwrap();
+    let latencies = std::mem::replace(
+        &mut *latencies.lock().unwrap(),
+        Percentiles::new(cli.percentiles()),
+    );
+    let handshakes = std::mem::replace(
+        &mut *handshakes.lock().unwrap(),
+        Percentiles::new(cli.percentiles()),
+    );
+    Ok(ReuseLeg {
+        successes,
+        latencies,
+        handshakes,
+        achieved_rate: cli.total as f64 / dispatch_elapsed.as_secs_f64(),
+    })
+}
+
+/// Send requests at the target rate until the first 2xx response, reconnecting
+/// whenever the server is not reachable yet.
+async fn wait_for_success(cli: &Cli, uri: &Uri) -> Result<Report, anyhow::Error> {
+    let start = Instant::now();
+    let give_up_at = match cli.max_wait {
+        Some(secs) if !secs.is_finite() || secs <= 0.0 => {
+            anyhow::bail!("--max-wait must be a positive number of seconds")
         }
+        Some(secs) => Some(start + Duration::from_secs_f64(secs)),
+        None => None,
+    };
+
+    let template = RequestTemplate::new(cli, uri)?;
+    let drain = DrainOptions::whole(cli.max_body_size, false);
+
+    let (succeeded, mut first_success) = tokio::sync::mpsc::unbounded_channel();
+    let mut sender: Option<SendRequest<RequestBody>> = None;
+    let mut attempts = 0;
+    let pacer = TokenBucket::new(cli.rate);
+    let give_up = async {
+        match give_up_at {
+            Some(at) => sleep_until(at.into()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(give_up);
+    let finished = loop {
+        if sender.as_ref().is_some_and(|sender| sender.is_closed()) {
+            sender = None;
+        }
+        if sender.is_none() {
+            // Don't let a hanging handshake outlast --max-wait
+            sender = match give_up_at {
+                Some(at) => timeout_at(at.into(), connect(uri, None))
+                    .await
+                    .ok()
+                    .and_then(Result::ok),
+                None => connect(uri, None).await.ok(),
+            };
+        }
+
+        if let Some(sender) = &sender {
+            let mut sender = sender.clone();
+            let req = template.build(attempts)?;
+            let drain = drain.clone();
+            let succeeded = succeeded.clone();
+            tokio::spawn(async move {
+                let enqueued = Instant::now();
+                let in_flight = Arc::new(AtomicUsize::new(0));
+                let result = make_request(
+                    &mut sender,
+                    req,
+                    in_flight,
+                    drain,
+                    enqueued,
+                    LatencyFrom::Body,
+                );
+                if let Ok(sample) = result.await {
+                    if sample.status.is_success() {
+                        let _ = succeeded.send((Instant::now(), sample.status));
+                    }
+                }
+            });
+        }
+        attempts += 1;
+
+        tokio::select! {
+            Some(success) = first_success.recv() => break Some(success),
+            _ = &mut give_up => break None,
+            _ = pacer.acquire() => {}
+        }
+    };
+
+    let Some((at, status)) = finished else {
+        anyhow::bail!(
+            "no successful response within {:.2?} ({} attempt(s))",
+            start.elapsed(),
+            attempts
+        );
     };
-    let average_in_flight = {
-        let total_samples: usize = in_flight_samples.iter().sum();
-        if in_flight_samples.len() > 0 {
-            total_samples as f64 / in_flight_samples.len() as f64
+    let elapsed = at - start;
+
+    let mut summary = String::new();
+    writeln!(
+        summary,
+        "first success: {} after {} ({} attempt(s))",
+        status,
+        cli.latency_format().format(elapsed),
+        attempts
+    )?;
+
+    Ok(Report {
+        summary,
+        target_rate: cli.rate,
+        achieved_rate: attempts as f64 / elapsed.as_secs_f64(),
+        success_rate: 100.0,
+        p99_latency: elapsed,
+        response_size: None,
+        stats: None,
+        shortfall: None,
+    })
+}
+
+/// Send a single request and print the response, like a quick curl sharing
+/// the connection and request options of a load test. JSON bodies are
+/// pretty-printed; the run fails unless the status is 2xx.
+async fn once(cli: &Cli, uri: &Uri) -> Result<Report, anyhow::Error> {
+    let req = RequestTemplate::new(cli, uri)?.build(0)?;
+    let drain = DrainOptions::whole(cli.max_body_size, true);
+
+    let mut sender = connect_at_startup(cli, uri, None).await?;
+    let sample = make_request(
+        &mut sender,
+        req,
+        Arc::new(AtomicUsize::new(0)),
+        drain,
+        Instant::now(),
+        cli.latency_from,
+    )
+    .await?;
+
+    let mut summary = String::new();
+    writeln!(summary, "status: {}", sample.status)?;
+    for (name, value) in &sample.headers {
+        writeln!(
+            summary,
+            "{}: {}",
+            name,
+            String::from_utf8_lossy(value.as_bytes())
+        )?;
+    }
+    writeln!(summary)?;
+    let body = sample.body.unwrap_or_default();
+    let shown = &body[..body.len().min(ONCE_BODY_LIMIT)];
+    match std::str::from_utf8(shown) {
+        Ok(text) => match pretty_json::pretty(text).filter(|_| shown.len() == body.len()) {
+            Some(pretty) => writeln!(summary, "{}", pretty)?,
+            None if text.is_empty() || text.ends_with('\n') => summary.push_str(text),
+            None => writeln!(summary, "{}", text)?,
+        },
+        Err(_) => writeln!(summary, "({} bytes of binary body)", shown.len())?,
+    }
+    if shown.len() < body.len() {
+        writeln!(
+            summary,
+            "({} more bytes not shown)",
+            body.len() - shown.len()
+        )?;
+    }
+
+    let shortfall =
+        (!sample.status.is_success()).then(|| format!("request returned status {}", sample.status));
+    Ok(Report {
+        summary,
+        target_rate: cli.rate,
+        achieved_rate: cli.rate,
+        success_rate: if shortfall.is_none() { 100.0 } else { 0.0 },
+        p99_latency: sample.duration,
+        response_size: None,
+        stats: None,
+        shortfall,
+    })
+}
+
+/// Search for the highest rate that meets --slo-p99: double the rate from
+/// --rate until a probe run misses the SLO, then bisect between the highest
+/// passing and the lowest failing rate.
+async fn find_max_rate(cli: &Cli, uri: &Uri) -> Result<Report, anyhow::Error> {
+    let slo = Duration::from_millis(cli.slo_p99.unwrap_or_default());
+    if !cli.probe_duration.is_finite() || cli.probe_duration <= 0.0 {
+        anyhow::bail!("--probe-duration must be a positive number of seconds");
+    }
+
+    let lf = cli.latency_format();
+    let mut probe_cli = cli.clone();
+    let mut trajectory = String::new();
+    let mut passing: Option<Report> = None;
+    let mut failing_rate: Option<f64> = None;
+    let mut rate = cli.rate;
+    let mut probes = 0;
+    while probes < MAX_RATE_PROBES {
+        probes += 1;
+        probe_cli.rate = rate;
+        probe_cli.total = ((rate * cli.probe_duration).ceil() as usize).max(1);
+        let report = run(&probe_cli, uri).await?;
+
+        // A probe passes when every request succeeded within the SLO and the
+        // client kept up with the rate
+        let passed = report.success_rate >= 100.0
+            && report.p99_latency <= slo
+            && report.achieved_rate >= 0.9 * report.target_rate;
+        writeln!(
+            trajectory,
+            "  {:.2} req/s: p99 {}, success {:.1}%, achieved {:.2} req/s, {}",
+            rate,
+            lf.format(report.p99_latency),
+            report.success_rate,
+            report.achieved_rate,
+            if passed { "pass" } else { "fail" }
+        )?;
+        if passed {
+            passing = Some(report);
         } else {
-            0.0
+            failing_rate = Some(rate);
+        }
+
+        let lower = passing.as_ref().map_or(0.0, |report| report.target_rate);
+        rate = match failing_rate {
+            None => rate * 2.0,
+            Some(upper) if upper - lower <= upper * RATE_SEARCH_PRECISION => break,
+            Some(upper) => (lower + upper) / 2.0,
+        };
+        // Without any passing rate the search heads towards zero; give up
+        // once a probe would no longer send a single request
+        if rate * cli.probe_duration < 1.0 {
+            break;
         }
+    }
+
+    let Some(best) = passing else {
+        anyhow::bail!(
+            "no probed rate down to {:.2} req/s kept p99 within {}\n{}",
+            failing_rate.unwrap_or(cli.rate),
+            lf.format(slo),
+            trajectory.trim_end()
+        );
     };
 
-    println!("success: {:.1}%", success_rate);
-    println!("median response time: {:.2?}", median_response_time);
-    println!("average in-flight: {:.2}", average_in_flight);
+    let mut summary = String::new();
+    writeln!(
+        summary,
+        "max rate within p99 {}: {:.2} req/s",
+        lf.format(slo),
+        best.target_rate
+    )?;
+    writeln!(summary, "p99 at that rate: {}", lf.format(best.p99_latency))?;
+    match failing_rate {
+        Some(upper) => writeln!(
+            summary,
+            "probes: {} ({:.2} req/s missed the SLO)",
+            probes, upper
+        )?,
+        None => writeln!(
+            summary,
+            "probes: {} (no probed rate missed the SLO)",
+            probes
+        )?,
+    }
+    if cli.verbose {
+        writeln!(summary, "search trajectory:")?;
+        summary.push_str(&trajectory);
+    }
+
+    Ok(Report { summary, ..best })
+}
+
+/// Write `contents` to a sibling temporary file and rename it into place, so
+/// readers never observe a partially written file.
+fn write_atomically(path: &Path, contents: &str) -> Result<(), anyhow::Error> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)?;
 
     Ok(())
 }
 
-async fn make_request(
-    sender: &mut hyper::client::conn::http2::SendRequest<Empty<Bytes>>,
+/// Returned when a response body exceeds `--max-body-size`.
+#[derive(Debug)]
+struct OversizedBody {
+    limit: usize,
+}
+
+impl fmt::Display for OversizedBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "response body exceeded {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for OversizedBody {}
+
+/// Returned when a request is dropped by `--cancel-after`.
+#[derive(Debug)]
+struct Cancelled {
+    after: Duration,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cancelled after {:?}", self.after)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Returned when a CONNECT request is answered with a non-2xx status.
+#[derive(Debug)]
+struct TunnelRefused {
+    status: StatusCode,
+}
+
+impl fmt::Display for TunnelRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tunnel not established (status {})",
+            self.status.as_u16()
+        )
+    }
+}
+
+impl std::error::Error for TunnelRefused {}
+
+/// Map a request error onto the category it is reported under.
+fn failure_category(err: &anyhow::Error) -> &'static str {
+    #[cfg(feature = "script")]
+    if err.is::<script::ScriptError>() {
+        return "script error";
+    }
+
+    if err.is::<Cancelled>() {
+        "cancelled"
+    } else if err.is::<OversizedBody>() {
+        "oversized body"
+    } else if err.is::<success::Rejected>() {
+        "rejected by success expression"
+    } else if err.is::<TunnelRefused>() {
+        "tunnel not established"
+    } else if err.is::<DeadlineExceeded>() {
+        "deadline exceeded"
+    } else if let Some(category) = stream_reset_category(err) {
+        category
+    } else {
+        "request error"
+    }
+}
+
+/// Categorize streams the server reset with RST_STREAM by their error code.
+fn stream_reset_category(err: &anyhow::Error) -> Option<&'static str> {
+    let h2_err = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<h2::Error>())?;
+    if !(h2_err.is_reset() && h2_err.is_remote()) {
+        return None;
+    }
+
+    Some(match h2_err.reason()? {
+        h2::Reason::NO_ERROR => "stream reset: NO_ERROR",
+        h2::Reason::PROTOCOL_ERROR => "stream reset: PROTOCOL_ERROR",
+        h2::Reason::INTERNAL_ERROR => "stream reset: INTERNAL_ERROR",
+        h2::Reason::FLOW_CONTROL_ERROR => "stream reset: FLOW_CONTROL_ERROR",
+        h2::Reason::STREAM_CLOSED => "stream reset: STREAM_CLOSED",
+        h2::Reason::FRAME_SIZE_ERROR => "stream reset: FRAME_SIZE_ERROR",
+        h2::Reason::REFUSED_STREAM => "stream reset: REFUSED_STREAM",
+        h2::Reason::CANCEL => "stream reset: CANCEL",
+        h2::Reason::COMPRESSION_ERROR => "stream reset: COMPRESSION_ERROR",
+        h2::Reason::CONNECT_ERROR => "stream reset: CONNECT_ERROR",
+        h2::Reason::ENHANCE_YOUR_CALM => "stream reset: ENHANCE_YOUR_CALM",
+        h2::Reason::INADEQUATE_SECURITY => "stream reset: INADEQUATE_SECURITY",
+        h2::Reason::HTTP_1_1_REQUIRED => "stream reset: HTTP_1_1_REQUIRED",
+        _ => "stream reset: unknown code",
+    })
+}
+
+/// Boundaries of the response time recorded for each request
+#[derive(Clone, Copy, ValueEnum)]
+enum LatencyFrom {
+    /// From when the pacer scheduled the request, including any wait for a
+    /// --max-concurrency slot, until the response body has been read
+    Enqueue,
+    /// From when the connection starts writing the request's HEADERS frame
+    /// until the response body has been read, leaving out the time the
+    /// request waits for the connection to take it, e.g. behind other
+    /// requests
+    Send,
+    /// From when the request is handed to the connection until the response
+    /// headers arrive (time to first byte)
+    Headers,
+    /// From when the request is handed to the connection until the response
+    /// body has been read
+    Body,
+}
+
+/// Measurements taken for a single completed request
+struct Sample {
+    duration: Duration,
+    status: StatusCode,
+    headers: HeaderMap,
+    body_size: usize,
+    /// The response body, only kept when requested
+    body: Option<Bytes>,
+    timeline: Timeline,
+}
+
+/// When a request passed each stage on its way through the client
+#[derive(Clone, Copy)]
+struct Timeline {
+    /// Scheduled by the pacer
+    enqueued: Instant,
+    /// Handed to the connection
+    sent: Instant,
+    /// Picked up by the connection, which starts writing its HEADERS frame
+    written: Instant,
+    headers: Instant,
+    /// Body read to the end
+    completed: Instant,
+}
+
+/// Create an HTTP request with the given headers and body, plus a HOST header.
+fn build_request(
+    method: Method,
     uri: Uri,
-    authority: &str,
-    in_flight: Arc<AtomicUsize>,
-) -> Result<Duration, anyhow::Error> {
-    let start = Instant::now();
+    headers: HeaderMap,
+    body: RequestBody,
+) -> Result<Request<RequestBody>, anyhow::Error> {
+    // The authority of our URL will be the hostname of the remote
+    let authority = uri.authority().unwrap().clone();
 
-    // Create an HTTP request with an empty body and a HOST header
-    let req = Request::builder()
+    let mut req = Request::builder()
+        .method(method)
         .uri(uri)
-        .header(hyper::header::HOST, authority)
-        .body(Empty::<Bytes>::new())?;
+        .header(hyper::header::HOST, authority.as_str())
+        .body(body)?;
+    req.headers_mut().extend(headers);
+
+    Ok(req)
+}
+
+/// The request sent over and over by the modes other than a full run: one
+/// method, target and header set, cycling through the request bodies.
+struct RequestTemplate {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    bodies: Vec<Bytes>,
+}
+
+impl RequestTemplate {
+    /// The request given on the command line, a POST if it has a body and a
+    /// GET otherwise unless --method says differently
+    fn new(cli: &Cli, uri: &Uri) -> Result<Self, anyhow::Error> {
+        let bodies = load_bodies(cli)?;
+        let method = cli.method.clone().unwrap_or(if bodies.is_empty() {
+            Method::GET
+        } else {
+            Method::POST
+        });
+        Ok(RequestTemplate {
+            method,
+            uri: request_base(cli, uri)?,
+            headers: headers::to_map(&user_headers(cli)?, cli.no_hpack_indexing),
+            bodies,
+        })
+    }
+
+    /// The request with the body for attempt `i`, sent in one piece.
+    fn build(&self, i: usize) -> Result<Request<RequestBody>, anyhow::Error> {
+        let body = match self.bodies.len() {
+            0 => Bytes::new(),
+            n => self.bodies[i % n].clone(),
+        };
+        build_request(
+            self.method.clone(),
+            self.uri.clone(),
+            self.headers.clone(),
+            RequestBody::chunked(body, 0, Duration::ZERO),
+        )
+    }
+}
+
+/// Statistics over the body chunks of `--streaming` responses
+struct StreamStats {
+    messages: usize,
+    /// Time between consecutive chunks of the same response
+    gaps: Percentiles,
+}
+
+/// How a response body is read once the headers arrive
+#[derive(Clone)]
+struct DrainOptions {
+    /// Abort the stream once more than this many bytes have been received
+    max_body_size: usize,
+    /// Keep the body in the resulting sample
+    keep_body: bool,
+    /// Count every body chunk as a streamed message
+    stream_stats: Option<Arc<Mutex<StreamStats>>>,
+    /// Close the stream after this long and treat the response as complete
+    max_open_time: Option<Duration>,
+    /// Drop the request after this long and fail it with [`Cancelled`]
+    cancel_after: Option<Duration>,
+    /// Add the size of every body chunk as it is read, whether or not the
+    /// request goes on to succeed
+    received: Option<Arc<AtomicUsize>>,
+}
+
+impl DrainOptions {
+    /// Read the whole body, up to `max_body_size` bytes, keeping it if
+    /// `keep_body` is set
+    fn whole(max_body_size: usize, keep_body: bool) -> Self {
+        DrainOptions {
+            max_body_size,
+            keep_body,
+            stream_stats: None,
+            max_open_time: None,
+            cancel_after: None,
+            received: None,
+        }
+    }
+}
+
+async fn make_request(
+    sender: &mut SendRequest<RequestBody>,
+    req: Request<RequestBody>,
+    in_flight: Arc<AtomicUsize>,
+    drain: DrainOptions,
+    enqueued: Instant,
+    latency_from: LatencyFrom,
+) -> Result<Sample, anyhow::Error> {
+    let start = Instant::now();
+    let written = req.body().written();
 
     // Await the response...
     in_flight.fetch_add(1, Ordering::SeqCst);
-    sender.send_request(req).await?;
+    let result = match drain.cancel_after {
+        // Dropping the response future resets its stream
+        Some(after) => timeout(after, send_and_drain(sender, req, drain))
+            .await
+            .unwrap_or_else(|_| Err(Cancelled { after }.into())),
+        None => send_and_drain(sender, req, drain).await,
+    };
     in_flight.fetch_sub(1, Ordering::SeqCst);
+    let (status, headers, headers_at, body_size, body) = result?;
+    let timeline = Timeline {
+        enqueued,
+        sent: start,
+        // Requests failing before the connection takes them never get here
+        written: written.get().copied().unwrap_or(start),
+        headers: headers_at,
+        completed: Instant::now(),
+    };
+
+    let duration = match latency_from {
+        LatencyFrom::Enqueue => timeline.completed - enqueued,
+        LatencyFrom::Send => timeline.completed - timeline.written,
+        LatencyFrom::Body => timeline.completed - start,
+        LatencyFrom::Headers => headers_at - start,
+    };
+
+    Ok(Sample {
+        duration,
+        status,
+        headers,
+        body_size,
+        body,
+        timeline,
+    })
+}
+
+/// Send `req` and read the response body according to `drain`, until it ends
+/// or `max_open_time` has passed since the request was sent. Returns the
+/// response status, headers, the time the headers arrived and the body size,
+/// plus the body if `keep_body` is set.
+async fn send_and_drain(
+    sender: &mut SendRequest<RequestBody>,
+    req: Request<RequestBody>,
+    drain: DrainOptions,
+) -> Result<(StatusCode, HeaderMap, Instant, usize, Option<Bytes>), anyhow::Error> {
+    let deadline = drain
+        .max_open_time
+        .map(|max| tokio::time::Instant::now() + max);
+
+    let response = sender.send_request(req).await?;
+    let headers_at = Instant::now();
+    let (parts, body) = response.into_parts();
+
+    let (size, body) = drain_body(body, &drain, deadline).await?;
+    Ok((parts.status, parts.headers, headers_at, size, body))
+}
+
+/// Read `body` according to `drain`, until it ends or `deadline` passes.
+/// Returns the body size, plus the body if `keep_body` is set.
+async fn drain_body<B>(
+    mut body: B,
+    drain: &DrainOptions,
+    deadline: Option<tokio::time::Instant>,
+) -> Result<(usize, Option<Bytes>), anyhow::Error>
+where
+    B: hyper::body::Body<Data = Bytes> + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    let DrainOptions {
+        max_body_size,
+        keep_body,
+        stream_stats,
+        received,
+        ..
+    } = drain;
+
+    let mut size = 0;
+    let mut kept = Vec::new();
+    let mut last_message: Option<Instant> = None;
+    loop {
+        let frame = match deadline {
+            // Dropping the body on expiry resets the stream
+            Some(deadline) => match timeout_at(deadline, body.frame()).await {
+                Ok(frame) => frame,
+                Err(_) => break,
+            },
+            None => body.frame().await,
+        };
+        let Some(frame) = frame else {
+            break;
+        };
+        if let Some(data) = frame?.data_ref() {
+            if let (Some(stream_stats), false) = (&stream_stats, data.is_empty()) {
+                let now = Instant::now();
+                let mut ss = stream_stats.lock().unwrap();
+                ss.messages += 1;
+                if let Some(last) = last_message {
+                    ss.gaps.record(now - last);
+                }
+                last_message = Some(now);
+            }
+            size += data.len();
+            if let Some(received) = &received {
+                received.fetch_add(data.len(), Ordering::SeqCst);
+            }
+            if size > *max_body_size {
+                return Err(OversizedBody {
+                    limit: *max_body_size,
+                }
+                .into());
+            }
+            if *keep_body {
+                kept.extend_from_slice(data);
+            }
+        }
+    }
+
+    Ok((size, keep_body.then(|| Bytes::from(kept))))
+}
+
+/// Hand out shares of the run to --workers worker processes and combine the
+/// summaries they report back.
+async fn coordinate(cli: &Cli, listen: &str) -> Result<Report, anyhow::Error> {
+    let workers = cli.workers.unwrap();
+    if workers == 0 {
+        anyhow::bail!("--workers must be at least 1");
+    }
+    if cli.total < workers {
+        anyhow::bail!("--total must be at least the number of --workers");
+    }
+
+    let summaries = distributed::coordinate(listen, workers, cli.rate, cli.total).await?;
+    let combined = distributed::combine(&summaries);
+
+    let lf = cli.latency_format();
+    let mut summary = String::new();
+    writeln!(summary, "workers: {}", workers)?;
+    for (n, worker) in summaries.iter().enumerate() {
+        writeln!(
+            summary,
+            "  worker {}: {} requests, {:.1}% success, {:.2} req/s, p99 {}",
+            n,
+            worker.requests,
+            worker.success_rate,
+            worker.achieved_rate,
+            lf.format(worker.p99_latency)
+        )?;
+    }
+    writeln!(summary, "success: {:.1}%", combined.success_rate)?;
+    writeln!(
+        summary,
+        "achieved rate: {:.2} req/s (combined)",
+        combined.achieved_rate
+    )?;
+    writeln!(
+        summary,
+        "p99 response time: {} (slowest worker)",
+        lf.format(combined.p99_latency)
+    )?;
+
+    Ok(Report {
+        summary,
+        target_rate: cli.rate,
+        achieved_rate: combined.achieved_rate,
+        success_rate: combined.success_rate,
+        p99_latency: combined.p99_latency,
+        response_size: None,
+        stats: None,
+        shortfall: None,
+    })
+}
+
+/// Run the share of a --coordinator's run it hands this worker, then report
+/// the results back to it.
+async fn run_worker(cli: &Cli, uri: &Uri, coordinator: &str) -> Result<Report, anyhow::Error> {
+    let (worker, assignment) = distributed::Worker::join(coordinator).await?;
+    let mut share = cli.clone();
+    share.rate = assignment.rate;
+    share.total = assignment.total;
+
+    let report = run(&share, uri).await?;
+    worker
+        .report(&distributed::WorkerSummary {
+            requests: assignment.total,
+            success_rate: report.success_rate,
+            achieved_rate: report.achieved_rate,
+            p99_latency: report.p99_latency,
+        })
+        .await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+
+    use super::*;
+
+    type Chunk = Result<Frame<Bytes>, std::io::Error>;
+
+    /// A body that yields what is sent into it and ends once the sender is
+    /// dropped
+    fn body() -> (
+        mpsc::UnboundedSender<Chunk>,
+        StreamBody<mpsc::UnboundedReceiver<Chunk>>,
+    ) {
+        let (sender, receiver) = mpsc::unbounded();
+        (sender, StreamBody::new(receiver))
+    }
+
+    fn chunk(data: &'static str) -> Chunk {
+        Ok(Frame::data(Bytes::from_static(data.as_bytes())))
+    }
 
-    Ok(start.elapsed())
+    #[tokio::test]
+    async fn max_open_time_completes_a_stream_left_open() {
+        let (sender, body) = body();
+        sender.unbounded_send(chunk("event 1\n")).unwrap();
+        let start = tokio::time::Instant::now();
+        let deadline = start + Duration::from_millis(50);
+
+        let drain = DrainOptions::whole(1024, true);
+        let (size, kept) = drain_body(body, &drain, Some(deadline)).await.unwrap();
+        assert_eq!((size, kept.as_deref()), (8, Some(&b"event 1\n"[..])));
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(50), "{:?}", waited);
+        assert!(waited < Duration::from_millis(500), "{:?}", waited);
+        // The body is dropped, which resets an HTTP/2 stream
+        assert!(sender.is_closed());
+    }
+
+    #[tokio::test]
+    async fn body_ending_before_max_open_time() {
+        let (sender, body) = body();
+        sender.unbounded_send(chunk("ab")).unwrap();
+        sender.unbounded_send(chunk("cd")).unwrap();
+        drop(sender);
+        let start = tokio::time::Instant::now();
+
+        let drain = DrainOptions::whole(1024, false);
+        let deadline = start + Duration::from_secs(10);
+        let (size, kept) = drain_body(body, &drain, Some(deadline)).await.unwrap();
+        assert_eq!((size, kept), (4, None));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn max_open_time_does_not_excuse_an_oversized_body() {
+        let (sender, body) = body();
+        sender.unbounded_send(chunk("0123456789")).unwrap();
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(50);
+
+        let drain = DrainOptions::whole(4, false);
+        let err = drain_body(body, &drain, Some(deadline)).await.unwrap_err();
+        assert!(err.is::<OversizedBody>(), "{}", err);
+        drop(sender);
+    }
 }
@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Boundary separating the parts, derived from `seed` so runs are reproducible
+pub fn boundary(seed: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    format!(
+        "http2-load-test-{:016x}{:016x}",
+        rng.gen::<u64>(),
+        rng.gen::<u64>()
+    )
+}
+
+/// `Content-Type` announcing a body built with `boundary`
+pub fn content_type(boundary: &str) -> Result<HeaderValue, anyhow::Error> {
+    Ok(HeaderValue::from_str(&format!(
+        "multipart/form-data; boundary={}",
+        boundary
+    ))?)
+}
+
+/// Assemble a `multipart/form-data` body from `--form FIELD=VALUE` and
+/// `--form-file FIELD=@PATH` arguments, fields first.
+pub fn build(fields: &[String], files: &[String], boundary: &str) -> Result<Bytes, anyhow::Error> {
+    let mut body = Vec::new();
+
+    for field in fields {
+        let (name, value) = split(field, "--form")?;
+        write!(
+            body,
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n",
+            boundary,
+            quote(name)
+        )?;
+        body.extend_from_slice(value.as_bytes());
+        body.extend_from_slice(b"\r\n");
+    }
+
+    for file in files {
+        let (name, path) = split(file, "--form-file")?;
+        let Some(path) = path.strip_prefix('@') else {
+            anyhow::bail!("--form-file {}: expected FIELD=@PATH", file);
+        };
+        let contents =
+            fs::read(path).map_err(|err| anyhow::anyhow!("failed to read {}: {}", path, err))?;
+        let filename = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        write!(
+            body,
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary,
+            quote(name),
+            quote(&filename)
+        )?;
+        body.extend_from_slice(&contents);
+        body.extend_from_slice(b"\r\n");
+    }
+
+    write!(body, "--{}--\r\n", boundary)?;
+    Ok(Bytes::from(body))
+}
+
+fn split<'a>(arg: &'a str, flag: &str) -> Result<(&'a str, &'a str), anyhow::Error> {
+    match arg.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name, value)),
+        _ => anyhow::bail!("{} {}: expected FIELD=VALUE", flag, arg),
+    }
+}
+
+/// Percent-encode the characters that would end a quoted parameter, as
+/// browsers do for form field and file names.
+fn quote(name: &str) -> String {
+    name.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn boundary_follows_the_seed() {
+        assert_eq!(boundary(1), boundary(1));
+        assert_ne!(boundary(1), boundary(2));
+        assert!(boundary(1).starts_with("http2-load-test-"));
+        assert_eq!(
+            content_type("b").unwrap(),
+            "multipart/form-data; boundary=b"
+        );
+    }
+
+    #[test]
+    fn builds_fields_then_files() {
+        let path = std::env::temp_dir().join(format!(
+            "http2-load-test-multipart-{}.bin",
+            std::process::id()
+        ));
+        fs::write(&path, b"\x00\x01").unwrap();
+        let file = format!("upload=@{}", path.display());
+        let body = build(&args(&["name=a=b", "empty="]), &[file], "XX").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let filename = path.file_name().unwrap().to_string_lossy();
+        let mut expected = b"--XX\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\na=b\r\n\
+            --XX\r\nContent-Disposition: form-data; name=\"empty\"\r\n\r\n\r\n"
+            .to_vec();
+        expected.extend_from_slice(
+            format!(
+                "--XX\r\nContent-Disposition: form-data; name=\"upload\"; filename=\"{}\"\r\n\
+                 Content-Type: application/octet-stream\r\n\r\n",
+                filename
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(b"\x00\x01\r\n--XX--\r\n");
+        assert_eq!(body, expected);
+    }
+
+    #[test]
+    fn quotes_names() {
+        let body = build(&args(&["a\"b\r\n=1"]), &[], "XX").unwrap();
+        assert!(body.starts_with(b"--XX\r\nContent-Disposition: form-data; name=\"a%22b%0D%0A\""));
+    }
+
+    #[test]
+    fn rejects_malformed_arguments() {
+        assert!(build(&args(&["novalue"]), &[], "XX").is_err());
+        assert!(build(&args(&["=value"]), &[], "XX").is_err());
+        assert!(build(&[], &args(&["file=path"]), "XX").is_err());
+        assert!(build(&[], &args(&["file=@/nonexistent/upload"]), "XX").is_err());
+    }
+}
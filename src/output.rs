@@ -0,0 +1,336 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use hyper::StatusCode;
+
+use crate::Report;
+
+/// Version of the `--output json` document. Fields are only ever added
+/// within a version; renaming, removing or retyping one needs a new version,
+/// offered under a new `--output` value next to the old one.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Format the report of a run is printed in
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// The human readable summary
+    Text,
+    /// A JSON object, see "JSON output" in the README
+    Json,
+}
+
+/// Percentiles of the `latency_ms` object, by field name
+pub const LATENCY_PERCENTILES: [(&str, f64); 4] =
+    [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("p999", 0.999)];
+
+/// The sections of a load run's report, given as structured fields of the
+/// JSON document. Other modes have none.
+pub struct RunStats {
+    pub dispatched: usize,
+    pub succeeded: usize,
+    /// Requests dropped by --cancel-after, which don't count as failed
+    pub cancelled: usize,
+    /// Response times at [`LATENCY_PERCENTILES`], or `None` if no request
+    /// was dispatched
+    pub latency: Option<[Duration; LATENCY_PERCENTILES.len()]>,
+    /// Responses of the successful requests by status
+    pub status_counts: Vec<(StatusCode, usize)>,
+    /// Failed requests by category
+    pub failures: Vec<(&'static str, usize)>,
+    /// The most frequent error messages with their counts
+    pub error_messages: Vec<(String, usize)>,
+}
+
+/// The `--output json` document for `report`, on one line
+pub fn json(report: &Report) -> String {
+    // A run that dispatched nothing has no latency to report
+    let p99_latency = match &report.stats {
+        Some(RunStats { latency: None, .. }) => f64::NAN,
+        _ => millis(report.p99_latency),
+    };
+    let mut out = format!(
+        r#"{{"schema_version":{},"target_rate":{},"achieved_rate":{},"success_rate":{},"p99_latency_ms":{},"shortfall":{}"#,
+        SCHEMA_VERSION,
+        number(report.target_rate),
+        number(report.achieved_rate),
+        number(report.success_rate),
+        number(p99_latency),
+        report
+            .shortfall
+            .as_deref()
+            .map_or("null".to_string(), string),
+    );
+    let _ = write!(
+        out,
+        r#","response_size":{}"#,
+        report
+            .response_size
+            .map_or("null".to_string(), |size| format!(
+                r#"{{"min":{},"median":{},"p99":{},"max":{},"total":{}}}"#,
+                size.min, size.median, size.p99, size.max, size.total
+            ))
+    );
+    match &report.stats {
+        Some(stats) => {
+            let _ = write!(
+                out,
+                r#","requests":{{"dispatched":{},"succeeded":{},"cancelled":{}}}"#,
+                stats.dispatched, stats.succeeded, stats.cancelled
+            );
+            let latency = stats.latency.map_or("null".to_string(), |latency| {
+                object(
+                    LATENCY_PERCENTILES
+                        .iter()
+                        .zip(latency)
+                        .map(|((name, _), latency)| (name.to_string(), number(millis(latency)))),
+                )
+            });
+            let _ = write!(out, r#","latency_ms":{}"#, latency);
+            let statuses = stats
+                .status_counts
+                .iter()
+                .map(|(status, count)| (status.as_str().to_string(), count.to_string()));
+            let _ = write!(out, r#","status_counts":{}"#, object(statuses));
+            let failures = stats
+                .failures
+                .iter()
+                .map(|(category, count)| (category.to_string(), count.to_string()));
+            let _ = write!(out, r#","failures":{}"#, object(failures));
+            let messages: Vec<String> = stats
+                .error_messages
+                .iter()
+                .map(|(message, count)| {
+                    format!(r#"{{"message":{},"count":{}}}"#, string(message), count)
+                })
+                .collect();
+            let _ = write!(out, r#","error_messages":[{}]"#, messages.join(","));
+        }
+        None => {
+            for field in [
+                "requests",
+                "latency_ms",
+                "status_counts",
+                "failures",
+                "error_messages",
+            ] {
+                let _ = write!(out, r#","{}":null"#, field);
+            }
+        }
+    }
+    let _ = write!(out, r#","summary":{}}}"#, string(&report.summary));
+    out
+}
+
+/// What went wrong in a failure reported by [`error`]
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorKind {
+    /// The target could not be reached
+    Connection,
+    /// The arguments or the files they name are invalid
+    Config,
+}
+
+/// The `--output json` object for a run that failed before its report, on
+/// one line. `error` is the outermost message, `detail` the full chain of
+/// causes.
+pub fn error(kind: ErrorKind, err: &anyhow::Error) -> String {
+    let kind = match kind {
+        ErrorKind::Connection => "connection",
+        ErrorKind::Config => "config",
+    };
+    format!(
+        r#"{{"schema_version":{},"error":{},"kind":"{}","detail":{}}}"#,
+        SCHEMA_VERSION,
+        string(&err.to_string()),
+        kind,
+        string(&format!("{:#}", err))
+    )
+}
+
+/// A JSON number, or `null` for values JSON can't represent, e.g. the rate of
+/// a run too short to time
+fn number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:.3}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// A JSON object of the already encoded `fields`
+fn object(fields: impl Iterator<Item = (String, String)>) -> String {
+    let fields: Vec<String> = fields
+        .map(|(name, value)| format!("{}:{}", string(&name), value))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// A JSON string literal holding `value`
+pub fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response_size::SizeSummary;
+
+    fn report(stats: Option<RunStats>) -> Report {
+        Report {
+            summary: "success: 97.0%\n".to_string(),
+            target_rate: 100.0,
+            achieved_rate: 99.5,
+            success_rate: 97.0,
+            p99_latency: Duration::from_micros(12_500),
+            response_size: Some(SizeSummary {
+                min: 2,
+                median: 2,
+                p99: 40,
+                max: 40,
+                total: 240,
+            }),
+            stats,
+            shortfall: None,
+        }
+    }
+
+    fn stats() -> RunStats {
+        RunStats {
+            dispatched: 100,
+            succeeded: 97,
+            cancelled: 0,
+            latency: Some([1, 5, 12, 20].map(Duration::from_millis)),
+            status_counts: vec![(StatusCode::OK, 95), (StatusCode::SERVICE_UNAVAILABLE, 2)],
+            failures: vec![("request error", 3)],
+            error_messages: vec![("connection \"reset\"".to_string(), 3)],
+        }
+    }
+
+    /// The top-level keys of a JSON object
+    fn keys(json: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut depth = 0;
+        let mut expect_key = false;
+        let mut chars = json.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' | '[' => {
+                    depth += 1;
+                    expect_key = depth == 1;
+                }
+                '}' | ']' => depth -= 1,
+                ',' => expect_key = depth == 1,
+                '"' => {
+                    let mut text = String::new();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' => text.extend(chars.next()),
+                            '"' => break,
+                            c => text.push(c),
+                        }
+                    }
+                    if std::mem::take(&mut expect_key) {
+                        keys.push(text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        keys
+    }
+
+    /// The report fields listed under "JSON output" in the README
+    fn documented_keys() -> Vec<String> {
+        let readme = include_str!("../README.md");
+        let section = readme.split("### JSON output").nth(1).unwrap();
+        let fields = section.split("\n\n").nth(2).unwrap();
+        fields
+            .lines()
+            .filter_map(|line| line.strip_prefix("- "))
+            .flat_map(|line| {
+                let names = line.split(':').next().unwrap();
+                names
+                    .split(" / ")
+                    .map(|name| name.trim_matches('`').to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn json_has_the_documented_fields() {
+        let documented = documented_keys();
+        assert_eq!(documented[0], "schema_version");
+        for report in [report(Some(stats())), report(None)] {
+            let json = json(&report);
+            assert!(crate::pretty_json::pretty(&json).is_some(), "{}", json);
+            assert_eq!(keys(&json), documented);
+        }
+    }
+
+    #[test]
+    fn json_layout() {
+        assert_eq!(
+            json(&report(Some(stats()))),
+            concat!(
+                r#"{"schema_version":1,"target_rate":100.000,"achieved_rate":99.500,"#,
+                r#""success_rate":97.000,"p99_latency_ms":12.500,"shortfall":null,"#,
+                r#""response_size":{"min":2,"median":2,"p99":40,"max":40,"total":240},"#,
+                r#""requests":{"dispatched":100,"succeeded":97,"cancelled":0},"#,
+                r#""latency_ms":{"p50":1.000,"p90":5.000,"p99":12.000,"p999":20.000},"#,
+                r#""status_counts":{"200":95,"503":2},"failures":{"request error":3},"#,
+                r#""error_messages":[{"message":"connection \"reset\"","count":3}],"#,
+                r#""summary":"success: 97.0%\n"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn other_modes_have_null_sections() {
+        let json = json(&report(None));
+        assert!(json.contains(r#""requests":null,"latency_ms":null,"status_counts":null,"failures":null,"error_messages":null"#));
+    }
+
+    #[test]
+    fn run_without_requests_has_no_latency() {
+        let json = json(&report(Some(RunStats {
+            dispatched: 0,
+            succeeded: 0,
+            latency: None,
+            status_counts: Vec::new(),
+            failures: Vec::new(),
+            error_messages: Vec::new(),
+            ..stats()
+        })));
+        assert!(json.contains(r#""p99_latency_ms":null,"#), "{}", json);
+        assert!(json.contains(r#""latency_ms":null,"#), "{}", json);
+    }
+
+    #[test]
+    fn error_object() {
+        let err = anyhow::anyhow!("refused").context("cannot reach localhost:1");
+        assert_eq!(
+            error(ErrorKind::Connection, &err),
+            r#"{"schema_version":1,"error":"cannot reach localhost:1","kind":"connection","detail":"cannot reach localhost:1: refused"}"#
+        );
+    }
+}
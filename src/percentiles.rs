@@ -0,0 +1,339 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use hdrhistogram::Histogram;
+use rand::Rng;
+use tdigest::TDigest;
+
+/// Number of centroids kept by the t-digest engine
+const TDIGEST_SIZE: usize = 100;
+
+/// How latency percentiles are computed
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PercentileEngine {
+    /// Keep every sample and sort them (exact, memory grows with the run)
+    Exact,
+    /// Stream samples into an HdrHistogram (bounded memory, fixed range)
+    Hdr,
+    /// Stream samples into a t-digest (bounded memory, any range)
+    Tdigest,
+}
+
+impl PercentileEngine {
+    pub fn name(self) -> &'static str {
+        match self {
+            PercentileEngine::Exact => "exact",
+            PercentileEngine::Hdr => "hdr",
+            PercentileEngine::Tdigest => "tdigest",
+        }
+    }
+}
+
+/// Percentile engine plus the range and precision of the HdrHistogram engine
+#[derive(Clone, Copy, Debug)]
+pub struct PercentileConfig {
+    pub engine: PercentileEngine,
+    /// Highest latency the HdrHistogram engine can track; larger samples are clamped
+    pub hdr_max: Duration,
+    /// Significant figures kept by the HdrHistogram engine
+    pub hdr_sigfigs: u8,
+}
+
+/// Collects latency samples and answers percentile queries over them.
+pub enum Percentiles {
+    Exact {
+        samples: Vec<Duration>,
+        sorted: bool,
+    },
+    Hdr {
+        histogram: Histogram<u64>,
+        /// Samples above the trackable range, recorded as its maximum
+        clamped: usize,
+    },
+    Tdigest(TDigest),
+}
+
+impl Percentiles {
+    pub fn new(config: PercentileConfig) -> Self {
+        match config.engine {
+            PercentileEngine::Exact => Percentiles::Exact {
+                samples: Vec::new(),
+                sorted: true,
+            },
+            PercentileEngine::Hdr => Percentiles::Hdr {
+                histogram: Histogram::new_with_bounds(
+                    1,
+                    config.hdr_max.as_nanos() as u64,
+                    config.hdr_sigfigs,
+                )
+                .expect("valid histogram bounds"),
+                clamped: 0,
+            },
+            PercentileEngine::Tdigest => Percentiles::Tdigest(TDigest::new_with_size(TDIGEST_SIZE)),
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        match self {
+            Percentiles::Exact { samples, sorted } => {
+                samples.push(duration);
+                *sorted = false;
+            }
+            Percentiles::Hdr { histogram, clamped } => {
+                let nanos = duration.as_nanos() as u64;
+                if nanos > histogram.high() {
+                    *clamped += 1;
+                }
+                histogram.saturating_record(nanos);
+            }
+            Percentiles::Tdigest(digest) => digest.push(duration.as_nanos() as f64),
+        }
+    }
+
+    /// Return the `p`-th percentile (0.0..=1.0), or zero if nothing was recorded.
+    pub fn percentile(&mut self, p: f64) -> Duration {
+        match self {
+            Percentiles::Exact { samples, sorted } => {
+                if !*sorted {
+                    samples.sort();
+                    *sorted = true;
+                }
+                exact_percentile(samples, p)
+            }
+            Percentiles::Hdr { histogram, .. } => {
+                if histogram.is_empty() {
+                    return Duration::new(0, 0);
+                }
+                Duration::from_nanos(histogram.value_at_quantile(p))
+            }
+            Percentiles::Tdigest(digest) => {
+                digest.flush();
+                let nanos = digest.estimate_quantile(p).unwrap_or(0.0);
+                Duration::from_nanos(nanos.max(0.0) as u64)
+            }
+        }
+    }
+
+    /// Number of samples the HdrHistogram engine clamped to its maximum
+    pub fn clamped(&self) -> usize {
+        match self {
+            Percentiles::Hdr { clamped, .. } => *clamped,
+            _ => 0,
+        }
+    }
+
+    /// Bootstrap a confidence interval at `level` (0.0..1.0) for the `p`-th
+    /// percentile from `resamples` resamples of the recorded samples. Only the
+    /// exact engine keeps the samples this needs; the others return `None`.
+    pub fn confidence_interval(
+        &self,
+        p: f64,
+        level: f64,
+        resamples: usize,
+        rng: &mut impl Rng,
+    ) -> Option<(Duration, Duration)> {
+        let Percentiles::Exact { samples, .. } = self else {
+            return None;
+        };
+        if samples.is_empty() {
+            return None;
+        }
+
+        let index = percentile_index(samples.len(), p);
+        let mut resample = Vec::with_capacity(samples.len());
+        let mut estimates = Vec::with_capacity(resamples);
+        for _ in 0..resamples {
+            resample.clear();
+            resample.extend((0..samples.len()).map(|_| samples[rng.gen_range(0..samples.len())]));
+            estimates.push(*resample.select_nth_unstable(index).1);
+        }
+        estimates.sort_unstable();
+
+        let tail = (1.0 - level) / 2.0;
+        Some((
+            exact_percentile(&estimates, tail),
+            exact_percentile(&estimates, 1.0 - tail),
+        ))
+    }
+}
+
+/// Return the `p`-th percentile (0.0..=1.0) of an already sorted slice, or
+/// the default value if it is empty.
+pub fn exact_percentile<T: Copy + Default>(sorted: &[T], p: f64) -> T {
+    if sorted.is_empty() {
+        return T::default();
+    }
+    sorted[percentile_index(sorted.len(), p)]
+}
+
+/// Return the `p`-th percentile (0.0..=1.0) of samples sorted by value, each
+/// counting as many times as its weight: the first value whose cumulative
+/// weight exceeds `p` of the total. With equal weights this matches
+/// [`exact_percentile`]. Returns zero if there is no weight at all.
+pub fn weighted_percentile(sorted: &[(Duration, u64)], p: f64) -> Duration {
+    let total: u64 = sorted.iter().map(|&(_, weight)| weight).sum();
+    let threshold = total as f64 * p;
+    let mut cumulative = 0;
+    for &(value, weight) in sorted {
+        cumulative += weight;
+        if cumulative as f64 > threshold {
+            return value;
+        }
+    }
+    // p = 1.0 ends up here, as does rounding right at the top
+    sorted
+        .iter()
+        .rev()
+        .find(|&&(_, weight)| weight > 0)
+        .map_or(Duration::ZERO, |&(value, _)| value)
+}
+
+/// Index of the `p`-th percentile in a sorted, non-empty slice of `len` items.
+fn percentile_index(len: usize, p: f64) -> usize {
+    ((len as f64 * p) as usize).min(len - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const QUANTILES: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+
+    fn config(engine: PercentileEngine) -> PercentileConfig {
+        PercentileConfig {
+            engine,
+            hdr_max: Duration::from_secs(60),
+            hdr_sigfigs: 3,
+        }
+    }
+
+    /// Skewed latencies between roughly 1ms and 1s, like a real run
+    fn latencies() -> Vec<Duration> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..10_000)
+            .map(|_| {
+                let ms = (rng.gen::<f64>() * 3.0).exp() * rng.gen_range(1.0..20.0);
+                Duration::from_secs_f64(ms / 1000.0)
+            })
+            .collect()
+    }
+
+    fn recorded(engine: PercentileEngine, samples: &[Duration]) -> Percentiles {
+        let mut percentiles = Percentiles::new(config(engine));
+        for &sample in samples {
+            percentiles.record(sample);
+        }
+        percentiles
+    }
+
+    fn assert_close(engine: PercentileEngine, tolerance: f64) {
+        let samples = latencies();
+        let mut exact = recorded(PercentileEngine::Exact, &samples);
+        let mut estimated = recorded(engine, &samples);
+        for p in QUANTILES {
+            let exact = exact.percentile(p).as_secs_f64();
+            let estimate = estimated.percentile(p).as_secs_f64();
+            let error = (estimate - exact).abs() / exact;
+            assert!(
+                error <= tolerance,
+                "{} p{}: {} vs exact {} ({:.2}% off)",
+                engine.name(),
+                p * 100.0,
+                estimate,
+                exact,
+                error * 100.0
+            );
+        }
+    }
+
+    #[test]
+    fn hdr_matches_exact_within_its_precision() {
+        // Three significant figures are 0.1%; the rest is the histogram
+        // ranking ties in the sparse tail differently
+        assert_close(PercentileEngine::Hdr, 0.01);
+    }
+
+    #[test]
+    fn tdigest_matches_exact_within_tolerance() {
+        assert_close(PercentileEngine::Tdigest, 0.05);
+    }
+
+    #[test]
+    fn empty_engines_report_zero() {
+        for engine in [
+            PercentileEngine::Exact,
+            PercentileEngine::Hdr,
+            PercentileEngine::Tdigest,
+        ] {
+            assert_eq!(
+                Percentiles::new(config(engine)).percentile(0.5),
+                Duration::ZERO
+            );
+        }
+    }
+
+    #[test]
+    fn hdr_counts_clamped_samples() {
+        let mut percentiles = Percentiles::new(PercentileConfig {
+            hdr_max: Duration::from_millis(10),
+            ..config(PercentileEngine::Hdr)
+        });
+        percentiles.record(Duration::from_millis(5));
+        percentiles.record(Duration::from_millis(50));
+        percentiles.record(Duration::from_secs(5));
+        assert_eq!(percentiles.clamped(), 2);
+        assert!(percentiles.percentile(1.0) <= Duration::from_millis(11));
+    }
+
+    #[test]
+    fn exact_percentile_picks_nearest_rank() {
+        let sorted: Vec<u32> = (1..=100).collect();
+        assert_eq!(exact_percentile(&sorted, 0.0), 1);
+        assert_eq!(exact_percentile(&sorted, 0.5), 51);
+        assert_eq!(exact_percentile(&sorted, 0.99), 100);
+        assert_eq!(exact_percentile(&sorted, 1.0), 100);
+        assert_eq!(exact_percentile::<u32>(&[], 0.5), 0);
+    }
+
+    #[test]
+    fn weighted_percentile_with_equal_weights_matches_exact() {
+        let mut samples = latencies();
+        samples.sort();
+        let weighted: Vec<_> = samples.iter().map(|&sample| (sample, 1)).collect();
+        for p in QUANTILES {
+            assert_eq!(
+                weighted_percentile(&weighted, p),
+                exact_percentile(&samples, p)
+            );
+        }
+    }
+
+    #[test]
+    fn weighted_percentile_follows_the_weight() {
+        let ms = Duration::from_millis;
+        // The slow sample carries 90% of the weight
+        let samples = [(ms(1), 1), (ms(2), 0), (ms(3), 9)];
+        assert_eq!(weighted_percentile(&samples, 0.05), ms(1));
+        assert_eq!(weighted_percentile(&samples, 0.5), ms(3));
+        assert_eq!(weighted_percentile(&samples, 1.0), ms(3));
+        assert_eq!(weighted_percentile(&[(ms(1), 0)], 0.5), Duration::ZERO);
+    }
+
+    #[test]
+    fn confidence_interval_brackets_the_estimate() {
+        let samples = latencies();
+        let mut exact = recorded(PercentileEngine::Exact, &samples);
+        let estimate = exact.percentile(0.9);
+        let mut rng = StdRng::seed_from_u64(1);
+        let (low, high) = exact
+            .confidence_interval(0.9, 0.95, 200, &mut rng)
+            .expect("exact engine keeps samples");
+        assert!(low <= estimate && estimate <= high);
+        assert!(recorded(PercentileEngine::Tdigest, &samples)
+            .confidence_interval(0.9, 0.95, 200, &mut rng)
+            .is_none());
+    }
+}
@@ -0,0 +1,104 @@
+/// Indent a JSON document two spaces per level. This only re-flows the
+/// tokens without validating them, and gives up with `None` when the input
+/// doesn't look like a JSON object or array, or its brackets don't match up.
+pub fn pretty(json: &str) -> Option<String> {
+    let json = json.trim();
+    if !(json.starts_with('{') || json.starts_with('[')) {
+        return None;
+    }
+
+    let mut out = String::with_capacity(json.len() * 2);
+    // Closing bracket expected for each open object or array
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = json.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                out.push(c);
+            }
+            '{' | '[' => {
+                out.push(c);
+                let close = if c == '{' { '}' } else { ']' };
+                skip_whitespace(&mut chars);
+                // Keep empty objects and arrays on one line
+                if chars.next_if_eq(&close).is_some() {
+                    out.push(close);
+                } else {
+                    open.push(close);
+                    newline(&mut out, open.len());
+                }
+            }
+            '}' | ']' => {
+                if open.pop()? != c {
+                    return None;
+                }
+                newline(&mut out, open.len());
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, open.len());
+            }
+            ':' => out.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+    }
+
+    (open.is_empty() && !in_string).then_some(out)
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indents_nested_documents() {
+        assert_eq!(
+            pretty(r#"{"a":[1,2],"b":{"c":"x"}}"#).unwrap(),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {\n    \"c\": \"x\"\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn keeps_strings_and_empty_containers_intact() {
+        assert_eq!(
+            pretty(r#" {"s":"a, {b}: \"c\"","e":[],"o":{}} "#).unwrap(),
+            "{\n  \"s\": \"a, {b}: \\\"c\\\"\",\n  \"e\": [],\n  \"o\": {}\n}"
+        );
+    }
+
+    #[test]
+    fn gives_up_on_other_input() {
+        assert_eq!(pretty("plain text"), None);
+        assert_eq!(pretty("42"), None);
+        assert_eq!(pretty(r#"{"a":[1}}"#), None);
+        assert_eq!(pretty(r#"{"a":"unterminated}"#), None);
+        assert_eq!(pretty("[[]"), None);
+    }
+}
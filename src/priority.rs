@@ -0,0 +1,99 @@
+use std::str::FromStr;
+
+use hyper::header::{HeaderName, HeaderValue};
+
+pub const PRIORITY: HeaderName = HeaderName::from_static("priority");
+
+/// Requests of a `--priority` class carry its RFC 9218 `priority` header and
+/// make up `weight` parts of the run
+#[derive(Clone, Debug)]
+pub struct PriorityClass {
+    pub value: HeaderValue,
+    pub weight: u32,
+}
+
+/// Parse a `--priority` class in the format `VALUE[@WEIGHT]`, e.g. `u=0,i@3`.
+/// VALUE lists the urgency `u=0` to `u=7` and the incremental flag `i`.
+impl FromStr for PriorityClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, weight) = match s.rsplit_once('@') {
+            Some((value, weight)) => {
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid weight: {}", weight))?;
+                (value.trim(), weight)
+            }
+            None => (s.trim(), 1),
+        };
+        if weight == 0 {
+            return Err("weight must be at least 1".to_string());
+        }
+        for param in value.split(',').map(str::trim) {
+            let valid = match param.split_once('=') {
+                Some(("u", urgency)) => {
+                    matches!(urgency, "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7")
+                }
+                Some(("i", flag)) => matches!(flag, "?0" | "?1"),
+                None => param == "i",
+                Some(_) => false,
+            };
+            if !valid {
+                return Err(format!(
+                    "invalid priority parameter {:?}, expected u=0 to u=7 or i",
+                    param
+                ));
+            }
+        }
+        let value = HeaderValue::from_str(value).map_err(|err| err.to_string())?;
+        Ok(PriorityClass { value, weight })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(s: &str) -> (String, u32) {
+        let class: PriorityClass = s.parse().unwrap();
+        (class.value.to_str().unwrap().to_string(), class.weight)
+    }
+
+    #[test]
+    fn parses_classes() {
+        assert_eq!(class("u=0"), ("u=0".to_string(), 1));
+        assert_eq!(class("u=0,i@3"), ("u=0,i".to_string(), 3));
+        assert_eq!(class(" u=7, i=?1 @ 10 "), ("u=7, i=?1".to_string(), 10));
+        assert_eq!(class("i"), ("i".to_string(), 1));
+        assert_eq!(class("i=?0@2"), ("i=?0".to_string(), 2));
+    }
+
+    #[test]
+    fn rejects_invalid_classes() {
+        for (s, expected) in [
+            ("u=0@0", "weight must be at least 1"),
+            ("u=0@x", "invalid weight: x"),
+            ("u=0@-1", "invalid weight: -1"),
+            (
+                "u=8",
+                r#"invalid priority parameter "u=8", expected u=0 to u=7 or i"#,
+            ),
+            (
+                "u=0,i=1",
+                r#"invalid priority parameter "i=1", expected u=0 to u=7 or i"#,
+            ),
+            (
+                "urgent",
+                r#"invalid priority parameter "urgent", expected u=0 to u=7 or i"#,
+            ),
+            (
+                "",
+                r#"invalid priority parameter "", expected u=0 to u=7 or i"#,
+            ),
+        ] {
+            assert_eq!(s.parse::<PriorityClass>().unwrap_err(), expected, "{}", s);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::fs;
+use std::path::Path;
+
+use pprof::protos::Message;
+use pprof::ProfilerGuard;
+
+/// Samples taken per second, off the round numbers timers tick at
+const FREQUENCY: i32 = 99;
+
+/// Start sampling the CPU usage of the whole process, all threads included.
+pub fn start() -> Result<ProfilerGuard<'static>, anyhow::Error> {
+    Ok(pprof::ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY)
+        // Unwinding through these while they hold locks can deadlock
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?)
+}
+
+/// Write the samples taken so far to `path`: a flamegraph if it ends in
+/// `.svg`, a pprof protobuf profile otherwise.
+pub fn write(guard: &ProfilerGuard, path: &Path) -> Result<(), anyhow::Error> {
+    let report = guard.report().build()?;
+    let mut content = Vec::new();
+    if path.extension().is_some_and(|ext| ext == "svg") {
+        report.flamegraph(&mut content)?;
+    } else {
+        report.pprof()?.write_to_vec(&mut content)?;
+    }
+    fs::write(path, content)
+        .map_err(|err| anyhow::anyhow!("failed to write {}: {}", path.display(), err))
+}
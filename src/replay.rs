@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use hyper::http::uri::PathAndQuery;
+use hyper::Method;
+
+/// A single request read from a replay log
+pub struct ReplayEntry {
+    /// Offset from the start of the log at which the request was made
+    pub offset: Duration,
+    pub method: Method,
+    pub path: PathAndQuery,
+    /// Client the request came from, read from a fourth column with
+    /// `--replay-per-client`
+    pub client: Option<String>,
+}
+
+/// The clients of a `--replay-per-client` log
+pub struct Clients {
+    /// Client identifiers, in order of their first request
+    pub names: Vec<String>,
+    /// Index into `names` of the client of each entry
+    pub of_entry: Vec<usize>,
+    /// Index of the last entry of each client, after which it disappears
+    pub last_entry: Vec<usize>,
+}
+
+impl Clients {
+    pub fn new(entries: &[ReplayEntry]) -> Clients {
+        let mut index = HashMap::new();
+        let mut clients = Clients {
+            names: Vec::new(),
+            of_entry: Vec::with_capacity(entries.len()),
+            last_entry: Vec::new(),
+        };
+        for (i, entry) in entries.iter().enumerate() {
+            let name = entry.client.as_deref().unwrap_or_default();
+            let client = *index.entry(name).or_insert_with(|| {
+                clients.names.push(name.to_string());
+                clients.last_entry.push(i);
+                clients.names.len() - 1
+            });
+            clients.of_entry.push(client);
+            clients.last_entry[client] = i;
+        }
+        clients
+    }
+}
+
+/// Load a replay log where each line is `<seconds> <METHOD> <path>`, followed
+/// by a `<client>` column when `per_client` is set.
+///
+/// Blank lines and lines starting with `#` are ignored, malformed lines are
+/// skipped with a warning. Entries are returned ordered by their offset, with
+/// entries at the same offset kept in log order.
+pub fn load(path: &Path, per_client: bool) -> Result<Vec<ReplayEntry>, anyhow::Error> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    let mut entries = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_line(line, per_client) {
+            Ok(entry) => entries.push(entry),
+            Err(err) => eprintln!(
+                "warning: skipping {}:{}: {}",
+                path.display(),
+                number + 1,
+                err
+            ),
+        }
+    }
+
+    if entries.is_empty() {
+        anyhow::bail!("replay log {} contains no requests", path.display());
+    }
+    entries.sort_by_key(|entry| entry.offset);
+
+    Ok(entries)
+}
+
+fn parse_line(line: &str, per_client: bool) -> Result<ReplayEntry, anyhow::Error> {
+    let mut fields = line.split_whitespace();
+    let (Some(offset), Some(method), Some(path)) = (fields.next(), fields.next(), fields.next())
+    else {
+        anyhow::bail!("expected `<seconds> <METHOD> <path>`");
+    };
+    let client = match (per_client, fields.next(), fields.next()) {
+        (false, None, _) => None,
+        (true, Some(client), None) => Some(client.to_string()),
+        (false, Some(_), _) => anyhow::bail!("expected `<seconds> <METHOD> <path>`"),
+        (true, _, _) => anyhow::bail!("expected `<seconds> <METHOD> <path> <client>`"),
+    };
+
+    let offset: f64 = offset.parse()?;
+    if !offset.is_finite() || offset < 0.0 {
+        anyhow::bail!("invalid timestamp {}", offset);
+    }
+
+    Ok(ReplayEntry {
+        offset: Duration::from_secs_f64(offset),
+        method: method.parse()?,
+        path: path.parse()?,
+        client,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Write `contents` to a file of its own in the temp directory
+    fn log_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "http2-load-test-replay-{}-{}",
+            std::process::id(),
+            name
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_a_line() {
+        let entry = parse_line("1.5 POST /orders?id=7", false).unwrap();
+        assert_eq!(entry.offset, Duration::from_millis(1500));
+        assert_eq!(entry.method, Method::POST);
+        assert_eq!(entry.path.as_str(), "/orders?id=7");
+        assert!(entry.client.is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "1.5 GET",
+            "-1 GET /",
+            "inf GET /",
+            "soon GET /",
+            "1 GET / extra",
+        ] {
+            assert!(parse_line(line, false).is_err(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn reads_the_client_column_only_per_client() {
+        let entry = parse_line("0 GET / alice", true).unwrap();
+        assert_eq!(entry.client.as_deref(), Some("alice"));
+        assert!(parse_line("0 GET /", true).is_err());
+        assert!(parse_line("0 GET / alice extra", true).is_err());
+    }
+
+    #[test]
+    fn load_sorts_stably_and_skips_bad_lines() {
+        let path = log_file(
+            "sort",
+            "# comment\n\n2 GET /c\n0 GET /a\nbroken\n0 GET /b\n",
+        );
+        let entries = load(&path, false).unwrap();
+        fs::remove_file(&path).unwrap();
+        let paths: Vec<_> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["/a", "/b", "/c"]);
+    }
+
+    #[test]
+    fn load_rejects_empty_and_missing_logs() {
+        let path = log_file("empty", "# nothing\n");
+        assert!(load(&path, false).is_err());
+        fs::remove_file(&path).unwrap();
+
+        let missing = std::env::temp_dir().join("http2-load-test-replay-missing");
+        let Err(err) = load(&missing, false) else {
+            panic!("loaded a missing log");
+        };
+        assert!(err.to_string().contains("http2-load-test-replay-missing"));
+    }
+
+    #[test]
+    fn clients_track_first_and_last_entries() {
+        let entries: Vec<_> = ["0 GET / a", "1 GET / b", "2 GET / a", "3 GET / c"]
+            .iter()
+            .map(|line| parse_line(line, true).unwrap())
+            .collect();
+        let clients = Clients::new(&entries);
+        assert_eq!(clients.names, ["a", "b", "c"]);
+        assert_eq!(clients.of_entry, [0, 1, 0, 2]);
+        assert_eq!(clients.last_entry, [2, 1, 3]);
+    }
+}
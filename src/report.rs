@@ -0,0 +1,837 @@
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::body::Bytes;
+use rand::rngs::StdRng;
+
+use crate::client_stats;
+use crate::headers::HeaderSet;
+use crate::health::HealthStats;
+use crate::hints;
+use crate::output::{RunStats, LATENCY_PERCENTILES};
+use crate::percentiles::{weighted_percentile, PercentileEngine};
+use crate::replay::Clients;
+use crate::schedule::Phase;
+use crate::soak::SoakStats;
+use crate::tally::Tally;
+use crate::tcp_info::TcpInfoStats;
+use crate::{Cli, Report, StreamStats, ADAPT_INTERVAL, TIMER_FLOOR};
+
+/// Number of resamples drawn for each --confidence interval
+const BOOTSTRAP_RESAMPLES: usize = 1000;
+
+/// Number of distinct error messages listed in the summary
+const TOP_ERROR_MESSAGES: usize = 5;
+
+/// Percentiles compared by --cost-weighted
+const COST_WEIGHTED_PERCENTILES: [(&str, f64); 3] = [("p50", 0.5), ("p90", 0.9), ("p99", 0.99)];
+
+/// Number of --replay-per-client clients listed in the summary, busiest first
+const TOP_REPLAY_CLIENTS: usize = 10;
+
+/// How a load run went, besides what its requests returned
+pub struct Run<'a> {
+    /// Requests per second the run aimed for
+    pub target_rate: f64,
+    /// Requests the run would have sent without stopping early
+    pub total_requests: usize,
+    pub dispatched: usize,
+    /// Requests that completed, successfully or not
+    pub completed: usize,
+    /// Connections opened at startup
+    pub connections: usize,
+    /// Connections open at the end, including ones added during the run
+    pub opened: usize,
+    pub dispatch_start: Instant,
+    /// Time spent dispatching, without the wait for the last responses
+    pub dispatch_elapsed: Duration,
+    /// Time from the first dispatch until the last response
+    pub elapsed: Duration,
+    pub bytes_sent: u64,
+    /// Flag of the byte budget that stopped the run early
+    pub budget_reached: Option<&'static str>,
+    /// Whether --target-successes stopped the run
+    pub target_reached: bool,
+    /// Requests in flight, sampled every 100ms
+    pub in_flight_samples: &'a [usize],
+    pub peak_fds: Option<usize>,
+    pub bodies: &'a [Bytes],
+    pub header_sets: &'a [HeaderSet],
+    /// Requests sent with each of `header_sets`
+    pub set_uses: &'a [usize],
+    pub clients: Option<&'a Clients>,
+    /// Most --replay-per-client clients connected at once
+    pub peak_clients: usize,
+    pub schedule: Option<&'a [Phase]>,
+    /// When each --rate-schedule phase started
+    pub phase_starts: &'a [Instant],
+    /// Connections open in each phase, with --connection-ramp
+    pub phase_connections: &'a [usize],
+    /// Time, mean latency and new rate of each --adaptive step
+    pub trajectory: &'a [(Duration, Duration, f64)],
+    pub stream_stats: Option<&'a Mutex<StreamStats>>,
+    pub soak: Option<&'a Mutex<SoakStats>>,
+    pub health: Option<&'a Mutex<HealthStats>>,
+    pub tcp_info: Option<&'a TcpInfoStats>,
+}
+
+/// Summarize a finished load run of `cli`, rendering the text summary and
+/// the figures of the JSON output from `tally` and `run`. `rng` draws the
+/// --confidence resamples.
+pub fn render(
+    cli: &Cli,
+    tally: &Tally,
+    run: Run,
+    rng: &mut StdRng,
+) -> Result<Report, anyhow::Error> {
+    let Run {
+        target_rate,
+        total_requests,
+        dispatched,
+        completed,
+        connections,
+        opened,
+        dispatch_start,
+        dispatch_elapsed,
+        elapsed,
+        bytes_sent,
+        budget_reached,
+        target_reached,
+        in_flight_samples,
+        peak_fds,
+        bodies,
+        header_sets,
+        set_uses,
+        clients,
+        peak_clients,
+        schedule,
+        phase_starts,
+        phase_connections,
+        trajectory,
+        stream_stats,
+        soak,
+        health,
+        tcp_info,
+    } = run;
+
+    let success_count = *tally.success_count.lock().unwrap();
+    let mut response_times = tally.response_times.lock().unwrap();
+    let latency_sum = *tally.latency_sum.lock().unwrap();
+    let below_timer_floor = *tally.below_timer_floor.lock().unwrap();
+    let failures = tally.failures.lock().unwrap();
+    let slowest = tally.slowest.lock().unwrap();
+    let response_size = tally.response_sizes.lock().unwrap().summary();
+    let mut phase_times = tally.phase_times.lock().unwrap();
+    let mut priority_times = tally.priority_times.lock().unwrap();
+    let mut client_times = tally.client_times.lock().unwrap();
+
+    // A run stopped before its first request, e.g. by --total 0, has no rate
+    let success_rate = if dispatched == 0 {
+        f64::NAN
+    } else {
+        (success_count as f64 / dispatched as f64) * 100.0
+    };
+    let median_response_time = response_times.percentile(0.5);
+    let achieved_rate = dispatched as f64 / dispatch_elapsed.as_secs_f64();
+    let average_in_flight = {
+        let total_samples: usize = in_flight_samples.iter().sum();
+        if !in_flight_samples.is_empty() {
+            total_samples as f64 / in_flight_samples.len() as f64
+        } else {
+            0.0
+        }
+    };
+
+    let lf = cli.latency_format();
+    let mut summary = String::new();
+    if dispatched == 0 {
+        writeln!(summary, "success: no requests dispatched")?;
+        writeln!(summary, "median response time: no requests dispatched")?;
+    } else {
+        writeln!(summary, "success: {:.1}%", success_rate)?;
+        writeln!(
+            summary,
+            "median response time: {}",
+            lf.format(median_response_time)
+        )?;
+    }
+    writeln!(summary, "average in-flight: {:.2}", average_in_flight)?;
+    if opened > 1 {
+        writeln!(
+            summary,
+            "achieved rate: {:.2} req/s ({:.2} req/s per connection)",
+            achieved_rate,
+            achieved_rate / opened as f64
+        )?;
+    } else {
+        writeln!(summary, "achieved rate: {:.2} req/s", achieved_rate)?;
+    }
+    {
+        // Both over the whole run, including the tail of in-flight requests
+        let secs = elapsed.as_secs_f64();
+        let gross = completed;
+        let (good, good_bytes) = *tally.goodput.lock().unwrap();
+        writeln!(
+            summary,
+            "throughput: gross {:.2} req/s, {:.0} B/s; goodput {:.2} req/s, {:.0} B/s ({:.1}% of responses)",
+            gross as f64 / secs,
+            tally.bytes_received.load(Ordering::SeqCst) as f64 / secs,
+            good as f64 / secs,
+            good_bytes as f64 / secs,
+            good as f64 / gross.max(1) as f64 * 100.0
+        )?;
+    }
+    if let Some(budget) = cli.max_bytes_sent {
+        writeln!(
+            summary,
+            "bytes sent: {} of {} budget ({:.1}%)",
+            bytes_sent,
+            budget,
+            bytes_sent as f64 / budget as f64 * 100.0
+        )?;
+    }
+    if let Some(budget) = cli.max_bytes_received {
+        let received = tally.bytes_received.load(Ordering::SeqCst);
+        writeln!(
+            summary,
+            "bytes received: {} of {} budget ({:.1}%)",
+            received,
+            budget,
+            received as f64 / budget as f64 * 100.0
+        )?;
+    }
+    if let Some(flag) = budget_reached {
+        writeln!(
+            summary,
+            "stopped early: {} reached after {} of {} requests",
+            flag, dispatched, total_requests
+        )?;
+    }
+    let mut shortfall = None;
+    if let Some(target) = cli.target_successes {
+        let success = if dispatched == 0 {
+            String::new()
+        } else {
+            format!(" ({:.1}% success)", success_rate)
+        };
+        // The check before each dispatch misses a target the last requests reach
+        if target_reached || success_count >= target {
+            writeln!(
+                summary,
+                "target successes: {} reached after {} attempts{}",
+                target, dispatched, success
+            )?;
+        } else {
+            writeln!(
+                summary,
+                "target successes: {} of {} after {} attempts{}",
+                success_count, target, dispatched, success
+            )?;
+            shortfall = Some(format!(
+                "only {} of {} requests succeeded within --max-attempts {}",
+                success_count, target, total_requests
+            ));
+        }
+    }
+    if let Some(max) = cli.max_connections {
+        writeln!(
+            summary,
+            "connections: {} (started with {}, --max-connections {})",
+            opened, connections, max
+        )?;
+    }
+    if success_count > 0 {
+        // Little's law: concurrency = rate * latency
+        let mean_latency = latency_sum.as_secs_f64() / success_count as f64;
+        let implied_concurrency = achieved_rate * mean_latency;
+        let required_concurrency = target_rate * mean_latency;
+        // Only worth a line when it may explain a missed rate, or on request
+        let relevant = achieved_rate < 0.9 * target_rate || cli.max_concurrency.is_some();
+        if cli.verbose || relevant {
+            write!(
+                summary,
+                "required concurrency: {:.2} (achieved rate x mean latency)",
+                implied_concurrency
+            )?;
+            if required_concurrency.is_finite() {
+                write!(summary, ", {:.2} for the target rate", required_concurrency)?;
+            }
+            writeln!(summary)?;
+        }
+        if let Some(max) = cli.max_concurrency {
+            // The cap binds when it is saturated yet below what the target needs
+            if average_in_flight >= 0.9 * max as f64 && required_concurrency > max as f64 {
+                writeln!(
+                    summary,
+                    "note: --max-concurrency {} is limiting throughput",
+                    max
+                )?;
+            }
+        }
+    }
+    if let Some(ms) = cli.cancel_after {
+        let cancelled = tally.cancelled.load(Ordering::SeqCst);
+        writeln!(
+            summary,
+            "cancelled after {}ms: {} ({:.1}%)",
+            ms,
+            cancelled,
+            cancelled as f64 / dispatched.max(1) as f64 * 100.0
+        )?;
+    }
+    for (category, count) in failures.iter() {
+        writeln!(summary, "failed ({}): {}", category, count)?;
+    }
+    let error_messages = tally.error_messages.lock().unwrap();
+    if !error_messages.is_empty() {
+        writeln!(
+            summary,
+            "error messages ({} distinct):",
+            error_messages.len()
+        )?;
+        for (message, count) in error_messages.top(TOP_ERROR_MESSAGES) {
+            writeln!(summary, "  {}x {}", count, message)?;
+        }
+        if error_messages.overflow() > 0 {
+            writeln!(
+                summary,
+                "  {} more failures with messages beyond the first {} distinct ones",
+                error_messages.overflow(),
+                error_messages.len()
+            )?;
+        }
+    }
+    // Flag runs where a tenth or more of the responses are too fast to time
+    if cli.verbose && success_count > 0 && below_timer_floor * 10 >= success_count {
+        writeln!(
+            summary,
+            "note: {:.1}% of responses took under {:?}; latency is at the measurement floor, \
+             the client and server are likely co-located",
+            below_timer_floor as f64 / success_count as f64 * 100.0,
+            TIMER_FLOOR
+        )?;
+    }
+    if let Some(stream_stats) = &stream_stats {
+        let mut stream_stats = stream_stats.lock().unwrap();
+        writeln!(
+            summary,
+            "messages: {} ({:.2} msg/s)",
+            stream_stats.messages,
+            stream_stats.messages as f64 / elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            summary,
+            "inter-message latency: median {}, p90 {}, p99 {}",
+            lf.format(stream_stats.gaps.percentile(0.5)),
+            lf.format(stream_stats.gaps.percentile(0.9)),
+            lf.format(stream_stats.gaps.percentile(0.99))
+        )?;
+    }
+    if let Some(size) = response_size {
+        writeln!(
+            summary,
+            "response size: min {} B, median {} B, p99 {} B, max {} B",
+            size.min, size.median, size.p99, size.max
+        )?;
+    }
+    if bodies.len() > 1 {
+        writeln!(summary, "request bodies: {} (round-robin)", bodies.len())?;
+    }
+    if cli.verbose || !matches!(cli.percentile_engine, PercentileEngine::Exact) {
+        writeln!(
+            summary,
+            "percentile engine: {}",
+            cli.percentile_engine.name()
+        )?;
+    }
+    if matches!(cli.percentile_engine, PercentileEngine::Hdr) {
+        let clamped = response_times.clamped();
+        writeln!(
+            summary,
+            "hdr range: up to {}, {} significant figures, {} sample(s) clamped{}",
+            lf.format(Duration::from_secs_f64(cli.max_latency_record)),
+            cli.sigfigs,
+            clamped,
+            if clamped > 0 {
+                " (raise --max-latency-record)"
+            } else {
+                ""
+            }
+        )?;
+    }
+    if let (Some(_), 0) = (&cli.synthetic, dispatched) {
+        writeln!(summary, "synthetic latency: no requests dispatched")?;
+    } else if let Some(distribution) = &cli.synthetic {
+        writeln!(summary, "synthetic latency (reported vs expected):")?;
+        for (name, p, expected) in distribution.expected() {
+            writeln!(
+                summary,
+                "  {}: {} vs {}",
+                name,
+                lf.format(response_times.percentile(p)),
+                lf.format(expected)
+            )?;
+        }
+    }
+    if let (Some(_), 0) = (cli.confidence, dispatched) {
+        writeln!(summary, "confidence intervals: no requests dispatched")?;
+    } else if let Some(level) = cli.confidence {
+        writeln!(
+            summary,
+            "confidence intervals ({}%, {} bootstrap resamples):",
+            level, BOOTSTRAP_RESAMPLES
+        )?;
+        for (name, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99)] {
+            let estimate = response_times.percentile(p);
+            if let Some((low, high)) =
+                response_times.confidence_interval(p, level / 100.0, BOOTSTRAP_RESAMPLES, rng)
+            {
+                writeln!(
+                    summary,
+                    "  {}: {} [{}, {}]",
+                    name,
+                    lf.format(estimate),
+                    lf.format(low),
+                    lf.format(high)
+                )?;
+            }
+        }
+    }
+    if let (Some(_), 0) = (&tally.cost_samples, dispatched) {
+        writeln!(summary, "cost-weighted latency: no requests dispatched")?;
+    } else if let Some(cost_samples) = &tally.cost_samples {
+        let mut cost_samples = cost_samples.lock().unwrap();
+        cost_samples.sort_unstable();
+        writeln!(summary, "cost-weighted latency (by bytes transferred):")?;
+        for (name, p) in COST_WEIGHTED_PERCENTILES {
+            writeln!(
+                summary,
+                "  {}: {} unweighted, {} weighted",
+                name,
+                lf.format(response_times.percentile(p)),
+                lf.format(weighted_percentile(&cost_samples, p))
+            )?;
+        }
+    }
+    if let Some(status_times) = &tally.status_times {
+        writeln!(summary, "latency by status:")?;
+        for (status, (count, times)) in status_times.lock().unwrap().iter_mut() {
+            writeln!(
+                summary,
+                "  {}: {} requests, median {}, p90 {}, p99 {}",
+                status.as_u16(),
+                count,
+                lf.format(times.percentile(0.5)),
+                lf.format(times.percentile(0.9)),
+                lf.format(times.percentile(0.99))
+            )?;
+        }
+    }
+    if !cli.priority.is_empty() {
+        writeln!(summary, "latency by priority:")?;
+        for (class, (count, times)) in cli.priority.iter().zip(priority_times.iter_mut()) {
+            writeln!(
+                summary,
+                "  {}: {} requests, median {}, p90 {}, p99 {}",
+                class.value.to_str().unwrap_or_default(),
+                count,
+                lf.format(times.percentile(0.5)),
+                lf.format(times.percentile(0.9)),
+                lf.format(times.percentile(0.99))
+            )?;
+        }
+    }
+    if !header_sets.is_empty() {
+        writeln!(summary, "header sets:")?;
+        for (n, (set, uses)) in header_sets.iter().zip(set_uses).enumerate() {
+            writeln!(
+                summary,
+                "  set {} (line {}): {} requests ({:.1}%)",
+                n + 1,
+                set.line,
+                uses,
+                *uses as f64 / dispatched.max(1) as f64 * 100.0
+            )?;
+        }
+    }
+    if let Some(clients) = &clients {
+        let mut requests = vec![0; clients.names.len()];
+        for &client in &clients.of_entry[..dispatched] {
+            requests[client] += 1;
+        }
+        // Clients that never got to send a request before an early stop
+        // don't count
+        let mut busiest: Vec<usize> = (0..clients.names.len())
+            .filter(|&client| requests[client] > 0)
+            .collect();
+        writeln!(
+            summary,
+            "replay clients: {}, at most {} connected at once",
+            busiest.len(),
+            peak_clients
+        )?;
+        busiest.sort_by_key(|&client| std::cmp::Reverse(requests[client]));
+        writeln!(summary, "latency by client:")?;
+        for &client in busiest.iter().take(TOP_REPLAY_CLIENTS) {
+            let (successes, times) = &mut client_times[client];
+            writeln!(
+                summary,
+                "  {}: {} of {} requests succeeded, median {}, p99 {}",
+                clients.names[client],
+                successes,
+                requests[client],
+                lf.format(times.percentile(0.5)),
+                lf.format(times.percentile(0.99))
+            )?;
+        }
+        if busiest.len() > TOP_REPLAY_CLIENTS {
+            writeln!(
+                summary,
+                "  ... and {} more clients",
+                busiest.len() - TOP_REPLAY_CLIENTS
+            )?;
+        }
+    }
+    if let Some(phases) = &schedule {
+        writeln!(summary, "rate schedule:")?;
+        let dispatch_end = dispatch_start + dispatch_elapsed;
+        let mut phase_offset = 0;
+        // A run stopped by a byte budget ends before the remaining phases start
+        for (n, (phase, (successes, times))) in phases
+            .iter()
+            .zip(phase_times.iter_mut())
+            .take(phase_starts.len())
+            .enumerate()
+        {
+            let start = phase_starts[n];
+            let end = phase_starts.get(n + 1).copied().unwrap_or(dispatch_end);
+            let requests = phase.requests().min(dispatched - phase_offset);
+            phase_offset += requests;
+            write!(
+                summary,
+                "  phase {} ({:?} at {} req/s): achieved {:.2} req/s, success {:.1}%, median {}, p99 {}",
+                n + 1,
+                phase.duration,
+                phase.rate,
+                requests as f64 / (end - start).as_secs_f64(),
+                *successes as f64 / requests as f64 * 100.0,
+                lf.format(times.percentile(0.5)),
+                lf.format(times.percentile(0.99))
+            )?;
+            match phase_connections.get(n) {
+                Some(open) => writeln!(summary, ", {} connection(s)", open)?,
+                None => writeln!(summary)?,
+            }
+        }
+    }
+    if cli.adaptive {
+        if cli.verbose {
+            writeln!(summary, "adaptive rate trajectory:")?;
+            for (at, mean, rate) in trajectory {
+                writeln!(
+                    summary,
+                    "  {:.0}s: mean {} -> {:.2} req/s",
+                    at.as_secs_f64(),
+                    lf.format(*mean),
+                    rate
+                )?;
+            }
+        }
+        // The rate saws around the operating point, so average out the later half
+        let settled = &trajectory[trajectory.len() / 2..];
+        if settled.is_empty() {
+            writeln!(
+                summary,
+                "adaptive rate: no adjustments, the run was shorter than {:?}",
+                ADAPT_INTERVAL
+            )?;
+        } else {
+            let stabilized =
+                settled.iter().map(|(_, _, rate)| rate).sum::<f64>() / settled.len() as f64;
+            writeln!(
+                summary,
+                "adaptive rate: stabilized at {:.2} req/s ({} adjustments, final {:.2} req/s)",
+                stabilized,
+                trajectory.len(),
+                settled.last().unwrap().2
+            )?;
+        }
+    }
+    if let Some(soak) = &soak {
+        summary.push_str(&soak.lock().unwrap().report(lf)?);
+    }
+    if let Some(health) = &health {
+        let mut health = health.lock().unwrap();
+        let median = health.latencies.percentile(0.5);
+        let p99 = health.latencies.percentile(0.99);
+        writeln!(
+            summary,
+            "health check: {}/{} healthy ({:.1}%), median {}, p99 {}",
+            health.healthy,
+            health.probes,
+            health.healthy as f64 / health.probes.max(1) as f64 * 100.0,
+            lf.format(median),
+            lf.format(p99)
+        )?;
+    }
+    if let Some(tcp_info) = &tcp_info {
+        // Catch the final counters of short runs
+        tcp_info.sample();
+        match tcp_info.summary() {
+            Some(stats) => writeln!(
+                summary,
+                "tcp info: average RTT {}, retransmits {}, max cwnd {} segments",
+                lf.format(stats.average_rtt),
+                stats.retransmits,
+                stats.max_cwnd
+            )?,
+            None => writeln!(summary, "tcp info: not available")?,
+        }
+    }
+    if cli.client_stats {
+        match client_stats::resource_usage() {
+            Some(usage) => writeln!(
+                summary,
+                "client: peak RSS {:.1} MiB, peak open fds {}, CPU time {:.2?} (user {:.2?}, system {:.2?})",
+                usage.peak_rss as f64 / (1024.0 * 1024.0),
+                peak_fds.map_or("unknown".to_string(), |fds| fds.to_string()),
+                usage.user_time + usage.system_time,
+                usage.user_time,
+                usage.system_time
+            )?,
+            None => writeln!(summary, "client: resource usage is not available on this platform")?,
+        }
+    }
+    if cli.hints {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let cpu_utilization = client_stats::resource_usage().map(|usage| {
+            (usage.user_time + usage.system_time).as_secs_f64()
+                / elapsed.as_secs_f64()
+                / cores as f64
+        });
+        let hints = hints::hints(&hints::Observations {
+            target_rate,
+            achieved_rate,
+            average_in_flight,
+            connections: opened,
+            max_concurrency: cli.max_concurrency,
+            median_latency: median_response_time,
+            p99_latency: response_times.percentile(0.99),
+            largest_request_body: bodies.iter().map(Bytes::len).max().unwrap_or(0),
+            failures: &failures,
+            cpu_utilization,
+        });
+        if hints.is_empty() {
+            writeln!(summary, "hints (heuristic): none")?;
+        } else {
+            writeln!(summary, "hints (heuristic):")?;
+            for hint in hints {
+                writeln!(summary, "  - {}", hint)?;
+            }
+        }
+    }
+    if let Some(pct) = cli.detail_sample_rate {
+        writeln!(
+            summary,
+            "detail sampled: {}% of requests in events, wire timing, slowest requests and failed request traces",
+            pct
+        )?;
+    }
+    if cli.top_slow > 0 {
+        writeln!(summary, "slowest requests:")?;
+        for request in slowest.slowest_first() {
+            write!(
+                summary,
+                "  #{}: {} (status {})",
+                request.index,
+                lf.format(request.duration),
+                request.status.as_u16()
+            )?;
+            match request.trace_id {
+                Some(trace_id) => writeln!(summary, ", trace {:032x}", trace_id)?,
+                None => writeln!(summary)?,
+            }
+        }
+    }
+    let failed_traces = tally.failed_traces.lock().unwrap();
+    if !failed_traces.is_empty() {
+        writeln!(summary, "failed request traces:")?;
+        for (index, category, trace_id) in failed_traces.iter() {
+            writeln!(
+                summary,
+                "  #{}: {}, trace {:032x}",
+                index, category, trace_id
+            )?;
+        }
+    }
+
+    let stats = RunStats {
+        dispatched,
+        succeeded: success_count,
+        cancelled: tally.cancelled.load(Ordering::SeqCst),
+        latency: (dispatched > 0)
+            .then(|| LATENCY_PERCENTILES.map(|(_, p)| response_times.percentile(p))),
+        status_counts: tally
+            .status_counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&status, &count)| (status, count))
+            .collect(),
+        failures: failures
+            .iter()
+            .map(|(&category, &count)| (category, count))
+            .collect(),
+        error_messages: error_messages
+            .top(TOP_ERROR_MESSAGES)
+            .into_iter()
+            .map(|(message, count)| (message.to_string(), count))
+            .collect(),
+    };
+
+    Ok(Report {
+        summary,
+        target_rate,
+        achieved_rate,
+        success_rate,
+        p99_latency: response_times.percentile(0.99),
+        response_size,
+        stats: Some(stats),
+        shortfall,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use hyper::header::HeaderMap;
+    use hyper::StatusCode;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::tally::RequestInfo;
+    use crate::{Sample, Timeline};
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["http2-load-test"].iter().chain(args)).unwrap()
+    }
+
+    /// A run of `dispatched` requests that took a second to dispatch
+    fn run(dispatched: usize) -> Run<'static> {
+        let start = Instant::now();
+        Run {
+            target_rate: dispatched as f64,
+            total_requests: dispatched,
+            dispatched,
+            completed: dispatched,
+            connections: 1,
+            opened: 1,
+            dispatch_start: start,
+            dispatch_elapsed: Duration::from_secs(1),
+            elapsed: Duration::from_secs(1),
+            bytes_sent: 0,
+            budget_reached: None,
+            target_reached: false,
+            in_flight_samples: &[],
+            peak_fds: None,
+            bodies: &[],
+            header_sets: &[],
+            set_uses: &[],
+            clients: None,
+            peak_clients: 0,
+            schedule: None,
+            phase_starts: &[],
+            phase_connections: &[],
+            trajectory: &[],
+            stream_stats: None,
+            soak: None,
+            health: None,
+            tcp_info: None,
+        }
+    }
+
+    fn request(index: usize) -> RequestInfo {
+        RequestInfo {
+            index,
+            body_size: 0,
+            priority: None,
+            client: None,
+            phase: None,
+            detailed: true,
+            trace_id: None,
+        }
+    }
+
+    fn sample(ms: u64) -> Sample {
+        let now = Instant::now();
+        Sample {
+            duration: Duration::from_millis(ms),
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body_size: 12,
+            body: None,
+            timeline: Timeline {
+                enqueued: now,
+                sent: now,
+                written: now,
+                headers: now,
+                completed: now,
+            },
+        }
+    }
+
+    fn render(cli: &Cli, tally: &Tally, run: Run) -> Report {
+        super::render(cli, tally, run, &mut StdRng::seed_from_u64(0)).unwrap()
+    }
+
+    #[test]
+    fn summarizes_the_requests() {
+        let cli = cli(&["--latency-unit", "ms", "localhost:80"]);
+        let tally = Tally::new(&cli, 0, 0);
+        for (index, ms) in [10, 20, 30].into_iter().enumerate() {
+            tally.success(&request(index), &sample(ms));
+        }
+        tally.failure(&request(3), &anyhow::anyhow!("connection reset"));
+
+        let report = render(&cli, &tally, run(4));
+        assert!(
+            report.summary.starts_with(concat!(
+                "success: 75.0%\n",
+                "median response time: 20.00ms\n",
+                "average in-flight: 0.00\n",
+                "achieved rate: 4.00 req/s\n",
+                "throughput: gross 4.00 req/s, 0 B/s; goodput 3.00 req/s, 36 B/s (75.0% of responses)\n",
+                "failed (request error): 1\n",
+                "error messages (1 distinct):\n",
+                "  1x connection reset\n",
+            )),
+            "{}",
+            report.summary
+        );
+        assert_eq!(report.success_rate, 75.0);
+        assert_eq!(report.p99_latency, Duration::from_millis(30));
+        let stats = report.stats.unwrap();
+        assert_eq!((stats.dispatched, stats.succeeded), (4, 3));
+        assert_eq!(stats.status_counts, [(StatusCode::OK, 3)]);
+        assert_eq!(stats.failures, [("request error", 1)]);
+    }
+
+    #[test]
+    fn run_without_requests() {
+        let cli = cli(&["--synthetic", "constant:1", "--total", "0", "localhost:80"]);
+        let tally = Tally::new(&cli, 0, 0);
+        let report = render(&cli, &tally, run(0));
+        assert!(report.success_rate.is_nan());
+        assert!(report.summary.contains("success: no requests dispatched\n"));
+        assert!(report
+            .summary
+            .contains("median response time: no requests dispatched\n"));
+        assert!(report
+            .summary
+            .contains("synthetic latency: no requests dispatched\n"));
+        assert!(!report.summary.contains("NaN"), "{}", report.summary);
+        assert_eq!(report.stats.unwrap().latency, None);
+    }
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use crate::percentiles::{PercentileConfig, Percentiles};
+
+/// Collects response body sizes, streaming them into the `--percentile-engine`
+/// so long runs don't keep every size. Sizes are recorded as nanosecond
+/// durations, one byte to the nanosecond, so the engines can be shared with
+/// latencies.
+pub struct ResponseSizes {
+    count: usize,
+    min: usize,
+    max: usize,
+    total: usize,
+    sizes: Percentiles,
+}
+
+/// Distribution of the response body sizes of a run, in bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SizeSummary {
+    pub min: usize,
+    pub median: usize,
+    pub p99: usize,
+    pub max: usize,
+    pub total: usize,
+}
+
+impl ResponseSizes {
+    /// Sizes up to `max_size`, the largest body a response may have; with
+    /// the hdr engine, larger ones are clamped to it.
+    pub fn new(config: PercentileConfig, max_size: usize) -> Self {
+        ResponseSizes {
+            count: 0,
+            min: usize::MAX,
+            max: 0,
+            total: 0,
+            sizes: Percentiles::new(PercentileConfig {
+                // The histogram needs a range of at least 1 to 2
+                hdr_max: Duration::from_nanos(max_size.max(2) as u64),
+                ..config
+            }),
+        }
+    }
+
+    pub fn record(&mut self, size: usize) {
+        self.count += 1;
+        self.min = self.min.min(size);
+        self.max = self.max.max(size);
+        self.total += size;
+        self.sizes.record(Duration::from_nanos(size as u64));
+    }
+
+    /// The distribution, or `None` if no size was recorded. Min, max and
+    /// total are exact; the median and p99 come from the engine.
+    pub fn summary(&mut self) -> Option<SizeSummary> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut percentile =
+            |p| (self.sizes.percentile(p).as_nanos() as usize).clamp(self.min, self.max);
+        Some(SizeSummary {
+            min: self.min,
+            median: percentile(0.5),
+            p99: percentile(0.99),
+            max: self.max,
+            total: self.total,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::percentiles::PercentileEngine;
+
+    fn sizes(engine: PercentileEngine, max_size: usize) -> ResponseSizes {
+        ResponseSizes::new(
+            PercentileConfig {
+                engine,
+                hdr_max: Duration::from_millis(1),
+                hdr_sigfigs: 3,
+            },
+            max_size,
+        )
+    }
+
+    #[test]
+    fn empty_has_no_summary() {
+        assert_eq!(sizes(PercentileEngine::Exact, 100).summary(), None);
+    }
+
+    #[test]
+    fn exact_summary() {
+        let mut recorded = sizes(PercentileEngine::Exact, 1000);
+        for size in 1..=100 {
+            recorded.record(size);
+        }
+        assert_eq!(
+            recorded.summary(),
+            Some(SizeSummary {
+                min: 1,
+                median: 51,
+                p99: 100,
+                max: 100,
+                total: 5050,
+            })
+        );
+    }
+
+    #[test]
+    fn bounded_engines_stay_close_to_exact() {
+        for engine in [PercentileEngine::Hdr, PercentileEngine::Tdigest] {
+            let mut recorded = sizes(engine, 4 * 1024 * 1024);
+            for size in 0..10_000 {
+                recorded.record(size * 100);
+            }
+            let summary = recorded.summary().unwrap();
+            assert_eq!((summary.min, summary.max), (0, 999_900));
+            assert_eq!(summary.total, 100 * 9_999 * 10_000 / 2);
+            // Sizes run past the 1ms latency range, so this also checks the
+            // hdr range comes from `max_size`
+            let median = summary.median as f64;
+            assert!((median - 500_000.0).abs() <= 5_000.0, "{}", median);
+            let p99 = summary.p99 as f64;
+            assert!((p99 - 990_000.0).abs() <= 10_000.0, "{}", p99);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+
+/// A period of the run during which requests are sent at a fixed rate
+pub struct Phase {
+    pub duration: Duration,
+    /// Requests per second
+    pub rate: f64,
+}
+
+impl Phase {
+    /// Number of requests sent during the phase
+    pub fn requests(&self) -> usize {
+        (self.duration.as_secs_f64() * self.rate).round() as usize
+    }
+}
+
+/// Connections to have open during phase `index` with `--connection-ramp`:
+/// `max` scaled by the phase's share of the peak rate, and never fewer than
+/// an earlier phase needed, so connections stay open once the load drops.
+pub fn ramp_connections(phases: &[Phase], index: usize, max: usize) -> usize {
+    let peak = phases.iter().map(|phase| phase.rate).fold(0.0, f64::max);
+    phases[..=index]
+        .iter()
+        .map(|phase| (max as f64 * phase.rate / peak).ceil() as usize)
+        .max()
+        .unwrap_or(1)
+        .clamp(1, max)
+}
+
+/// Load a rate schedule where each line is `<duration>:<rate>`, e.g. `30s:100`.
+///
+/// Durations take an `ms`, `s`, `m` or `h` suffix and default to seconds.
+/// Blank lines and lines starting with `#` are ignored. Phases are returned
+/// in file order.
+pub fn load(path: &Path) -> Result<Vec<Phase>, anyhow::Error> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    let mut phases = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let phase = parse_line(line)
+            .map_err(|err| anyhow::anyhow!("{}:{}: {}", path.display(), number + 1, err))?;
+        phases.push(phase);
+    }
+
+    if phases.is_empty() {
+        anyhow::bail!("rate schedule {} contains no phases", path.display());
+    }
+
+    Ok(phases)
+}
+
+fn parse_line(line: &str) -> Result<Phase, anyhow::Error> {
+    let Some((duration, rate)) = line.split_once(':') else {
+        anyhow::bail!("expected `<duration>:<rate>`");
+    };
+
+    let duration = parse_duration(duration.trim())?;
+    let rate: f64 = rate.trim().parse()?;
+    if !rate.is_finite() || rate <= 0.0 {
+        anyhow::bail!("invalid rate {}", rate);
+    }
+
+    let phase = Phase { duration, rate };
+    if phase.requests() == 0 {
+        anyhow::bail!("phase sends no requests");
+    }
+    Ok(phase)
+}
+
+fn parse_duration(duration: &str) -> Result<Duration, anyhow::Error> {
+    let (value, scale) = if let Some(value) = duration.strip_suffix("ms") {
+        (value, 0.001)
+    } else if let Some(value) = duration.strip_suffix('s') {
+        (value, 1.0)
+    } else if let Some(value) = duration.strip_suffix('m') {
+        (value, 60.0)
+    } else if let Some(value) = duration.strip_suffix('h') {
+        (value, 3600.0)
+    } else {
+        (duration, 1.0)
+    };
+
+    let secs = value.parse::<f64>()? * scale;
+    if !secs.is_finite() || secs <= 0.0 {
+        anyhow::bail!("invalid duration {}", duration);
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phase(secs: u64, rate: f64) -> Phase {
+        Phase {
+            duration: Duration::from_secs(secs),
+            rate,
+        }
+    }
+
+    #[test]
+    fn parses_duration_suffixes() {
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        for invalid in ["0s", "-1m", "soon", "ms"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_phases() {
+        let parsed = parse_line(" 2m : 50 ").unwrap();
+        assert_eq!(parsed.duration, Duration::from_secs(120));
+        assert_eq!(parsed.rate, 50.0);
+        assert_eq!(parsed.requests(), 6000);
+        for invalid in ["30s", "30s:0", "30s:-5", "30s:fast", "100ms:1"] {
+            assert!(parse_line(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn ramp_follows_the_peak_and_never_shrinks() {
+        let phases = [
+            phase(10, 10.0),
+            phase(10, 50.0),
+            phase(10, 100.0),
+            phase(10, 20.0),
+        ];
+        let ramp: Vec<_> = (0..phases.len())
+            .map(|index| ramp_connections(&phases, index, 10))
+            .collect();
+        assert_eq!(ramp, [1, 5, 10, 10]);
+    }
+
+    #[test]
+    fn ramp_opens_at_least_one_connection() {
+        let phases = [phase(10, 1.0), phase(10, 1000.0)];
+        assert_eq!(ramp_connections(&phases, 0, 4), 1);
+    }
+}
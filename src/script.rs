@@ -0,0 +1,166 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, StatusCode, Uri};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+/// Returned when a script hook fails while the run is in progress.
+#[derive(Debug)]
+pub struct ScriptError(String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "script error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// A rhai script that generates requests and inspects responses.
+///
+/// The script must define `build_request(ctx)`, returning a map with any of
+/// the keys `method`, `path`, `headers` (a map) and `body`; missing keys keep
+/// the values from the command line. `ctx` holds the shared context plus the
+/// request `index`.
+///
+/// It may also define `on_response(ctx, resp)`, where `resp` has the keys
+/// `status`, `headers` and `body`. If it returns a map, its entries are
+/// merged into the shared context seen by later requests.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    has_on_response: bool,
+    context: Mutex<Map>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let mut engine = Engine::new();
+        // Use the release limits in debug builds too, so nesting a few maps
+        // inside a hook doesn't fail depending on how the tool was built
+        engine.set_max_expr_depths(64, 32);
+        // stdout is kept for the report, --events and --output json
+        engine.on_print(|text| eprintln!("{}", text));
+        engine.on_debug(|text, _, pos| eprintln!("{:?} | {}", pos, text));
+        let ast = engine
+            .compile_file(path.into())
+            .map_err(|err| anyhow::anyhow!("failed to compile {}: {}", path.display(), err))?;
+
+        let defines = |name: &str| ast.iter_functions().any(|f| f.name == name);
+        if !defines("build_request") {
+            anyhow::bail!("{} does not define build_request(ctx)", path.display());
+        }
+        let has_on_response = defines("on_response");
+
+        Ok(Script {
+            engine,
+            ast,
+            has_on_response,
+            context: Mutex::new(Map::new()),
+        })
+    }
+
+    /// Whether responses need to be buffered for `on_response`.
+    pub fn wants_response(&self) -> bool {
+        self.has_on_response
+    }
+
+    /// Run `build_request` for the request at `index`, overriding the given
+    /// request parts with whatever the script returns. Any failure, including
+    /// an invalid method, path or header it returns, is a [`ScriptError`].
+    pub fn build_request(
+        &self,
+        index: usize,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Result<(Method, Uri, HeaderMap, Bytes), anyhow::Error> {
+        let mut ctx = self.context.lock().unwrap().clone();
+        ctx.insert("index".into(), Dynamic::from_int(index as i64));
+
+        let request: Map = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "build_request", (ctx,))
+            .map_err(|err| ScriptError(err.to_string()))?;
+
+        apply(request, method, uri, headers, body)
+            .map_err(|err| ScriptError(err.to_string()).into())
+    }
+
+    /// Run `on_response`, if defined, and merge its result into the context.
+    pub fn on_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        if !self.has_on_response {
+            return Ok(());
+        }
+
+        let mut response_headers = Map::new();
+        for (name, value) in headers {
+            response_headers.insert(
+                name.as_str().into(),
+                String::from_utf8_lossy(value.as_bytes())
+                    .into_owned()
+                    .into(),
+            );
+        }
+
+        let mut response = Map::new();
+        response.insert("status".into(), Dynamic::from_int(status.as_u16() as i64));
+        response.insert("headers".into(), response_headers.into());
+        response.insert(
+            "body".into(),
+            String::from_utf8_lossy(body).into_owned().into(),
+        );
+
+        let ctx = self.context.lock().unwrap().clone();
+        let result: Dynamic = self
+            .engine
+            .call_fn(&mut Scope::new(), &self.ast, "on_response", (ctx, response))
+            .map_err(|err| ScriptError(err.to_string()))?;
+
+        if let Some(updates) = result.try_cast::<Map>() {
+            self.context.lock().unwrap().extend(updates);
+        }
+
+        Ok(())
+    }
+}
+
+/// Apply the fields of the map returned by `build_request` to the request.
+fn apply(
+    request: Map,
+    mut method: Method,
+    mut uri: Uri,
+    mut headers: HeaderMap,
+    mut body: Bytes,
+) -> Result<(Method, Uri, HeaderMap, Bytes), anyhow::Error> {
+    for (key, value) in request {
+        match key.as_str() {
+            "method" => method = value.to_string().parse()?,
+            "path" => uri = crate::with_path(&uri, &value.to_string().parse()?)?,
+            "headers" => {
+                let Some(map) = value.try_cast::<Map>() else {
+                    anyhow::bail!("`headers` must be a map");
+                };
+                for (name, value) in map {
+                    headers.insert(
+                        HeaderName::from_bytes(name.as_bytes())?,
+                        HeaderValue::from_str(&value.to_string())?,
+                    );
+                }
+            }
+            "body" => body = Bytes::from(value.to_string()),
+            other => anyhow::bail!("unknown key `{}` returned by build_request", other),
+        }
+    }
+
+    Ok((method, uri, headers, body))
+}
@@ -0,0 +1,96 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+use hyper::StatusCode;
+
+/// A single request retained for the slowest-requests report
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlowRequest {
+    pub duration: Duration,
+    pub index: usize,
+    pub status: StatusCode,
+    /// Trace ID sent with --trace-context
+    pub trace_id: Option<u128>,
+}
+
+/// Keeps the `limit` slowest requests seen so far in a fixed-size min-heap.
+pub struct SlowestRequests {
+    limit: usize,
+    heap: BinaryHeap<Reverse<SlowRequest>>,
+}
+
+impl SlowestRequests {
+    pub fn new(limit: usize) -> Self {
+        SlowestRequests {
+            limit,
+            heap: BinaryHeap::with_capacity(limit + 1),
+        }
+    }
+
+    pub fn record(&mut self, request: SlowRequest) {
+        if self.limit == 0 {
+            return;
+        }
+        self.heap.push(Reverse(request));
+        if self.heap.len() > self.limit {
+            // Evict the fastest of the retained requests
+            self.heap.pop();
+        }
+    }
+
+    /// Return the retained requests, slowest first.
+    pub fn slowest_first(&self) -> Vec<SlowRequest> {
+        let mut requests: Vec<SlowRequest> = self.heap.iter().map(|r| r.0).collect();
+        requests.sort_by(|a, b| b.cmp(a));
+        requests
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(index: usize, ms: u64) -> SlowRequest {
+        SlowRequest {
+            duration: Duration::from_millis(ms),
+            index,
+            status: StatusCode::OK,
+            trace_id: None,
+        }
+    }
+
+    /// Index and duration in ms of the retained requests, slowest first
+    fn kept(slowest: &SlowestRequests) -> Vec<(usize, u64)> {
+        slowest
+            .slowest_first()
+            .iter()
+            .map(|request| (request.index, request.duration.as_millis() as u64))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_slowest() {
+        let mut slowest = SlowestRequests::new(3);
+        for (index, ms) in [5, 40, 1, 30, 7, 50, 2, 30].into_iter().enumerate() {
+            slowest.record(request(index, ms));
+        }
+        // Equally slow requests are ordered by index, the later one first
+        assert_eq!(kept(&slowest), [(5, 50), (1, 40), (7, 30)]);
+    }
+
+    #[test]
+    fn fewer_requests_than_the_limit() {
+        let mut slowest = SlowestRequests::new(5);
+        slowest.record(request(0, 3));
+        slowest.record(request(1, 9));
+        assert_eq!(kept(&slowest), [(1, 9), (0, 3)]);
+    }
+
+    #[test]
+    fn zero_limit_keeps_nothing() {
+        let mut slowest = SlowestRequests::new(0);
+        slowest.record(request(0, 3));
+        assert!(slowest.slowest_first().is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::latency::LatencyFormat;
+use crate::percentiles::{PercentileConfig, PercentileEngine, Percentiles};
+
+/// Width of the buckets the trend is fitted over
+const BUCKET: Duration = Duration::from_secs(60);
+
+/// Fitted p99 increase, in percent, that is reported as drift
+const P99_DRIFT_WARNING: f64 = 20.0;
+
+/// Fitted error rate increase, in percentage points, reported as drift
+const ERROR_RATE_DRIFT_WARNING: f64 = 1.0;
+
+/// Per-minute latency and error counts for `--soak-report`
+pub struct SoakStats {
+    start: Instant,
+    percentiles: PercentileConfig,
+    buckets: Vec<Bucket>,
+}
+
+struct Bucket {
+    requests: usize,
+    failures: usize,
+    latencies: Percentiles,
+}
+
+impl SoakStats {
+    /// Buckets from `start` on, with the `percentiles` engine if it is
+    /// bounded. The exact one would keep a second copy of every sample of
+    /// the soak, so its buckets use a t-digest instead.
+    pub fn new(start: Instant, percentiles: PercentileConfig) -> Self {
+        let engine = match percentiles.engine {
+            PercentileEngine::Exact => PercentileEngine::Tdigest,
+            bounded => bounded,
+        };
+        SoakStats {
+            start,
+            percentiles: PercentileConfig {
+                engine,
+                ..percentiles
+            },
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Record a request completing now, with its latency if it succeeded.
+    pub fn record(&mut self, latency: Option<Duration>) {
+        self.record_at(self.start.elapsed(), latency);
+    }
+
+    /// Record a request completing `elapsed` into the soak.
+    fn record_at(&mut self, elapsed: Duration, latency: Option<Duration>) {
+        let index = (elapsed.as_secs() / BUCKET.as_secs()) as usize;
+        while self.buckets.len() <= index {
+            self.buckets.push(Bucket {
+                requests: 0,
+                failures: 0,
+                latencies: Percentiles::new(self.percentiles),
+            });
+        }
+
+        let bucket = &mut self.buckets[index];
+        bucket.requests += 1;
+        match latency {
+            Some(latency) => bucket.latencies.record(latency),
+            None => bucket.failures += 1,
+        }
+    }
+
+    /// Fit a linear trend to the per-minute p99 and error rate and describe
+    /// it, warning about upward drift.
+    pub fn report(&mut self, lf: LatencyFormat) -> Result<String, anyhow::Error> {
+        let mut p99s = Vec::new();
+        let mut error_rates = Vec::new();
+        for (minute, bucket) in self.buckets.iter_mut().enumerate() {
+            if bucket.requests == 0 {
+                continue;
+            }
+            let x = minute as f64;
+            error_rates.push((x, bucket.failures as f64 / bucket.requests as f64 * 100.0));
+            if bucket.failures < bucket.requests {
+                p99s.push((x, bucket.latencies.percentile(0.99).as_secs_f64()));
+            }
+        }
+
+        let mut report = String::new();
+        if error_rates.len() < 3 {
+            writeln!(
+                report,
+                "soak trend: needs at least 3 minutes with completed requests"
+            )?;
+            return Ok(report);
+        }
+        let minutes = self.buckets.len();
+
+        if let Some((first, last)) = fit(&p99s) {
+            let first = first.max(0.0);
+            let last = last.max(0.0);
+            let change = if first > 0.0 {
+                (last - first) / first * 100.0
+            } else {
+                0.0
+            };
+            writeln!(
+                report,
+                "soak p99 trend: {} -> {} ({:+.1}% over {} min)",
+                lf.format(Duration::from_secs_f64(first)),
+                lf.format(Duration::from_secs_f64(last)),
+                change,
+                minutes
+            )?;
+            if change >= P99_DRIFT_WARNING {
+                writeln!(
+                    report,
+                    "warning: p99 increased {:.1}% over {} min",
+                    change, minutes
+                )?;
+            }
+        }
+
+        if let Some((first, last)) = fit(&error_rates) {
+            let first = first.max(0.0);
+            let last = last.max(0.0);
+            writeln!(
+                report,
+                "soak error rate trend: {:.2}% -> {:.2}% ({:+.2} points over {} min)",
+                first,
+                last,
+                last - first,
+                minutes
+            )?;
+            if last - first >= ERROR_RATE_DRIFT_WARNING {
+                writeln!(
+                    report,
+                    "warning: error rate increased {:.2} points over {} min",
+                    last - first,
+                    minutes
+                )?;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Least-squares line through `points`, evaluated at the first and last x.
+fn fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let (first, last) = (points.first()?.0, points.last()?.0);
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    if variance == 0.0 {
+        return None;
+    }
+    let covariance: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let slope = covariance / variance;
+    let at = |x: f64| mean_y + slope * (x - mean_x);
+
+    Some((at(first), at(last)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::latency::LatencyUnit;
+
+    fn close(actual: Option<(f64, f64)>, expected: (f64, f64)) {
+        let (first, last) = actual.unwrap();
+        assert!(
+            (first - expected.0).abs() < 1e-9 && (last - expected.1).abs() < 1e-9,
+            "{:?}",
+            actual
+        );
+    }
+
+    #[test]
+    fn fit_known_slope() {
+        close(
+            fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0), (3.0, 7.0)]),
+            (1.0, 7.0),
+        );
+        // A step is smoothed into the line closest to it
+        close(
+            fit(&[(0.0, 2.0), (1.0, 2.0), (2.0, 6.0), (3.0, 6.0)]),
+            (1.6, 6.4),
+        );
+    }
+
+    #[test]
+    fn fit_flat() {
+        close(fit(&[(0.0, 5.0), (1.0, 5.0), (4.0, 5.0)]), (5.0, 5.0));
+    }
+
+    #[test]
+    fn fit_needs_a_spread() {
+        assert_eq!(fit(&[]), None);
+        assert_eq!(fit(&[(2.0, 1.0), (2.0, 3.0)]), None);
+    }
+
+    fn stats() -> SoakStats {
+        SoakStats::new(
+            Instant::now(),
+            PercentileConfig {
+                engine: PercentileEngine::Exact,
+                hdr_max: Duration::from_secs(60),
+                hdr_sigfigs: 3,
+            },
+        )
+    }
+
+    fn report(stats: &mut SoakStats) -> String {
+        stats
+            .report(LatencyFormat {
+                unit: LatencyUnit::Ms,
+                precision: 0,
+            })
+            .unwrap()
+    }
+
+    fn minute(n: u64) -> Duration {
+        BUCKET * n as u32 + Duration::from_secs(1)
+    }
+
+    #[test]
+    fn under_three_minutes_has_no_trend() {
+        let mut stats = stats();
+        // Minutes without completed requests don't count
+        for n in [0, 3, 3] {
+            stats.record_at(minute(n), Some(Duration::from_millis(10)));
+        }
+        assert_eq!(
+            report(&mut stats),
+            "soak trend: needs at least 3 minutes with completed requests\n"
+        );
+    }
+
+    #[test]
+    fn steady_soak_has_no_warning() {
+        let mut stats = stats();
+        for n in 0..5 {
+            for _ in 0..99 {
+                stats.record_at(minute(n), Some(Duration::from_millis(10)));
+            }
+            stats.record_at(minute(n), None);
+        }
+        assert_eq!(
+            report(&mut stats),
+            concat!(
+                "soak p99 trend: 10ms -> 10ms (+0.0% over 5 min)\n",
+                "soak error rate trend: 1.00% -> 1.00% (+0.00 points over 5 min)\n"
+            )
+        );
+    }
+
+    #[test]
+    fn drift_is_warned_about() {
+        let mut stats = stats();
+        for n in 0..5 {
+            // p99 climbs 10ms a minute, failures a point a minute
+            for _ in 0..(100 - n) {
+                stats.record_at(minute(n), Some(Duration::from_millis(10 * (n + 1))));
+            }
+            for _ in 0..n {
+                stats.record_at(minute(n), None);
+            }
+        }
+        let report = report(&mut stats);
+        assert!(
+            report.contains("soak p99 trend: 10ms -> 50ms (+400.0% over 5 min)\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains("warning: p99 increased 400.0% over 5 min\n"));
+        assert!(report.contains("soak error rate trend: 0.00% -> 4.00%"));
+        assert!(report.contains("warning: error rate increased 4.00 points over 5 min\n"));
+    }
+
+    #[test]
+    fn exact_engine_buckets_are_bounded() {
+        let mut stats = stats();
+        stats.record_at(minute(0), Some(Duration::from_millis(1)));
+        assert!(matches!(
+            stats.buckets[0].latencies,
+            Percentiles::Tdigest(_)
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::time::Duration;
+
+use evalexpr::{
+    build_operator_tree, ContextWithMutableFunctions, ContextWithMutableVariables,
+    DefaultNumericTypes, Function, HashMapContext, Node, Value,
+};
+use hyper::header::HeaderMap;
+use hyper::StatusCode;
+
+/// Returned when a response does not satisfy `--success-expr`.
+#[derive(Debug)]
+pub struct Rejected;
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "response rejected by --success-expr")
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// A boolean expression deciding whether a response counts as a success.
+///
+/// The expression can use the variables `status`, `latency_ms`, `body_size`
+/// and `body`, plus the functions `header("name")`, which returns the header
+/// value or an empty string, and `str::contains(haystack, needle)`.
+pub struct SuccessExpr {
+    tree: Node<DefaultNumericTypes>,
+}
+
+impl SuccessExpr {
+    /// Parse `expr` and check it evaluates to a boolean on a sample response.
+    pub fn parse(expr: &str) -> Result<Self, anyhow::Error> {
+        let tree = build_operator_tree(expr)
+            .map_err(|err| anyhow::anyhow!("invalid --success-expr: {}", err))?;
+        let success_expr = SuccessExpr { tree };
+
+        success_expr
+            .evaluate(StatusCode::OK, Duration::ZERO, &HeaderMap::new(), b"")
+            .map_err(|err| anyhow::anyhow!("invalid --success-expr: {}", err))?;
+
+        Ok(success_expr)
+    }
+
+    pub fn evaluate(
+        &self,
+        status: StatusCode,
+        latency: Duration,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<bool, anyhow::Error> {
+        let mut context = HashMapContext::<DefaultNumericTypes>::new();
+        context.set_value("status".into(), Value::from_int(status.as_u16() as i64))?;
+        context.set_value(
+            "latency_ms".into(),
+            Value::from_float(latency.as_secs_f64() * 1000.0),
+        )?;
+        context.set_value("body_size".into(), Value::from_int(body.len() as i64))?;
+        context.set_value(
+            "body".into(),
+            Value::from(String::from_utf8_lossy(body).into_owned()),
+        )?;
+
+        let headers = headers.clone();
+        context.set_function(
+            "header".into(),
+            Function::new(move |argument| {
+                let name = argument.as_string()?;
+                let value = headers
+                    .get(name.as_str())
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                    .unwrap_or_default();
+                Ok(Value::from(value))
+            }),
+        )?;
+        context.set_function(
+            "str::contains".into(),
+            Function::new(|argument| {
+                let arguments = argument.as_fixed_len_tuple(2)?;
+                let haystack = arguments[0].as_string()?;
+                let needle = arguments[1].as_string()?;
+                Ok(Value::from(haystack.contains(&needle)))
+            }),
+        )?;
+
+        Ok(self.tree.eval_boolean_with_context(&context)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::HeaderValue;
+
+    use super::*;
+
+    fn accepts(expr: &str, status: u16, latency_ms: u64, headers: &HeaderMap, body: &str) -> bool {
+        SuccessExpr::parse(expr)
+            .unwrap()
+            .evaluate(
+                StatusCode::from_u16(status).unwrap(),
+                Duration::from_millis(latency_ms),
+                headers,
+                body.as_bytes(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn checks_status_and_latency() {
+        let expr = "status < 400 && latency_ms < 500";
+        let none = HeaderMap::new();
+        assert!(accepts(expr, 200, 20, &none, ""));
+        assert!(!accepts(expr, 503, 20, &none, ""));
+        assert!(!accepts(expr, 200, 500, &none, ""));
+    }
+
+    #[test]
+    fn checks_headers() {
+        let expr = r#"header("x-cache") == "HIT""#;
+        let mut headers = HeaderMap::new();
+        assert!(!accepts(expr, 200, 1, &headers, ""));
+        headers.insert("x-cache", HeaderValue::from_static("HIT"));
+        assert!(accepts(expr, 200, 1, &headers, ""));
+        headers.insert("x-cache", HeaderValue::from_static("MISS"));
+        assert!(!accepts(expr, 200, 1, &headers, ""));
+    }
+
+    #[test]
+    fn checks_the_body() {
+        let expr = r#"str::contains(body, "\"ok\":true") && body_size > 2"#;
+        let none = HeaderMap::new();
+        assert!(accepts(expr, 200, 1, &none, r#"{"ok":true}"#));
+        assert!(!accepts(expr, 200, 1, &none, r#"{"ok":false}"#));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in [
+            "status <",
+            "status + 1",
+            "unknown_variable == 1",
+            r#"header(1) == "x""#,
+            r#"str::contains(body) == true"#,
+        ] {
+            let err = SuccessExpr::parse(expr).err().expect(expr).to_string();
+            assert!(err.starts_with("invalid --success-expr: "), "{}", err);
+        }
+    }
+
+    #[test]
+    fn rejection_is_its_own_failure() {
+        let err: anyhow::Error = Rejected.into();
+        assert!(err.is::<Rejected>());
+        assert_eq!(err.to_string(), "response rejected by --success-expr");
+    }
+}
@@ -0,0 +1,214 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::header::HeaderMap;
+use hyper::StatusCode;
+use rand::Rng;
+use tokio::time::sleep;
+
+use crate::{Sample, Timeline};
+
+/// Percentiles whose expected values are reported, with the standard normal
+/// quantile of each
+const EXPECTED: [(&str, f64, f64); 3] = [
+    ("p50", 0.5, 0.0),
+    ("p90", 0.9, 1.281_551_565_544_600_4),
+    ("p99", 0.99, 2.326_347_874_040_840_8),
+];
+
+/// Latency distribution of `--synthetic` requests, in milliseconds
+#[derive(Clone, Copy, Debug)]
+pub enum LatencyDistribution {
+    Constant(f64),
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    Normal {
+        mean: f64,
+        std_dev: f64,
+    },
+    /// Log-normal with the given median and standard deviation of the log
+    LogNormal {
+        median: f64,
+        sigma: f64,
+    },
+    Exponential {
+        mean: f64,
+    },
+}
+
+/// Parse `constant:MS`, `uniform:MIN:MAX`, `normal:MEAN:STDDEV`,
+/// `lognormal:MEDIAN:SIGMA` or `exponential:MEAN`.
+impl FromStr for LatencyDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default();
+        let params = parts
+            .map(|param| {
+                param
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|value| value.is_finite() && *value >= 0.0)
+                    .ok_or_else(|| format!("invalid parameter {:?}", param))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let distribution = match (kind, params.as_slice()) {
+            ("constant", &[ms]) => LatencyDistribution::Constant(ms),
+            ("uniform", &[min, max]) if min <= max => LatencyDistribution::Uniform { min, max },
+            ("normal", &[mean, std_dev]) => LatencyDistribution::Normal { mean, std_dev },
+            ("lognormal", &[median, sigma]) => LatencyDistribution::LogNormal { median, sigma },
+            ("exponential", &[mean]) => LatencyDistribution::Exponential { mean },
+            _ => {
+                return Err(format!(
+                    "expected constant:MS, uniform:MIN:MAX, normal:MEAN:STDDEV, \
+                     lognormal:MEDIAN:SIGMA or exponential:MEAN, got {:?}",
+                    s
+                ))
+            }
+        };
+        Ok(distribution)
+    }
+}
+
+impl LatencyDistribution {
+    /// Draw a latency. Normal draws below zero are clamped to zero.
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let ms = match *self {
+            LatencyDistribution::Constant(ms) => ms,
+            LatencyDistribution::Uniform { min, max } => min + (max - min) * rng.gen::<f64>(),
+            LatencyDistribution::Normal { mean, std_dev } => mean + std_dev * standard_normal(rng),
+            LatencyDistribution::LogNormal { median, sigma } => {
+                median * (sigma * standard_normal(rng)).exp()
+            }
+            LatencyDistribution::Exponential { mean } => -mean * (1.0 - rng.gen::<f64>()).ln(),
+        };
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+
+    /// The exact p50, p90 and p99 of the distribution, to check the reported
+    /// percentiles against
+    pub fn expected(&self) -> Vec<(&'static str, f64, Duration)> {
+        EXPECTED
+            .iter()
+            .map(|&(name, p, z)| {
+                let ms = match *self {
+                    LatencyDistribution::Constant(ms) => ms,
+                    LatencyDistribution::Uniform { min, max } => min + (max - min) * p,
+                    LatencyDistribution::Normal { mean, std_dev } => mean + std_dev * z,
+                    LatencyDistribution::LogNormal { median, sigma } => median * (sigma * z).exp(),
+                    LatencyDistribution::Exponential { mean } => -mean * (1.0 - p).ln(),
+                };
+                (name, p, Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+            })
+            .collect()
+    }
+}
+
+/// A standard normal draw, by the Box-Muller transform
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    // Keep the first uniform away from zero, whose log is infinite
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Stand in for a request: stay in flight for `latency`, then complete with
+/// a 200 whose duration is exactly `latency`, so the stats see the drawn
+/// value rather than the timer's rounding of it.
+pub async fn request(latency: Duration, in_flight: Arc<AtomicUsize>, enqueued: Instant) -> Sample {
+    in_flight.fetch_add(1, Ordering::SeqCst);
+    let sent = Instant::now();
+    sleep(latency).await;
+    in_flight.fetch_sub(1, Ordering::SeqCst);
+    let completed = Instant::now();
+
+    Sample {
+        duration: latency,
+        status: StatusCode::OK,
+        headers: HeaderMap::new(),
+        body_size: 0,
+        body: None,
+        timeline: Timeline {
+            enqueued,
+            sent,
+            written: sent,
+            headers: completed,
+            completed,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn parses_every_distribution() {
+        for spec in [
+            "constant:5",
+            "uniform:1:9",
+            "normal:10:2",
+            "lognormal:10:0.5",
+            "exponential:4",
+        ] {
+            assert!(spec.parse::<LatencyDistribution>().is_ok(), "{:?}", spec);
+        }
+        for spec in [
+            "constant",
+            "constant:1:2",
+            "uniform:9:1",
+            "normal:-1:2",
+            "exponential:nan",
+            "pareto:1",
+        ] {
+            assert!(spec.parse::<LatencyDistribution>().is_err(), "{:?}", spec);
+        }
+    }
+
+    /// Draw enough samples that their percentiles settle near the exact ones
+    fn assert_samples_match_expected(spec: &str, tolerance: f64) {
+        let distribution: LatencyDistribution = spec.parse().unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut samples: Vec<_> = (0..50_000).map(|_| distribution.sample(&mut rng)).collect();
+        samples.sort();
+        for (name, p, expected) in distribution.expected() {
+            let drawn = crate::percentiles::exact_percentile(&samples, p).as_secs_f64();
+            let expected = expected.as_secs_f64();
+            assert!(
+                (drawn - expected).abs() <= tolerance * expected,
+                "{} {}: drew {} expected {}",
+                spec,
+                name,
+                drawn,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn samples_follow_the_expected_percentiles() {
+        assert_samples_match_expected("constant:5", 0.0);
+        assert_samples_match_expected("uniform:10:20", 0.02);
+        assert_samples_match_expected("normal:50:5", 0.02);
+        assert_samples_match_expected("lognormal:20:0.5", 0.05);
+        assert_samples_match_expected("exponential:10", 0.05);
+    }
+
+    #[test]
+    fn normal_draws_never_go_negative() {
+        let distribution: LatencyDistribution = "normal:1:10".parse().unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        // Duration can't be negative, so the draw must clamp rather than panic
+        for _ in 0..1000 {
+            distribution.sample(&mut rng);
+        }
+    }
+}
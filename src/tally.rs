@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::StatusCode;
+
+use crate::error_messages::ErrorMessages;
+use crate::percentiles::{PercentileConfig, Percentiles};
+use crate::response_size::ResponseSizes;
+use crate::slowest::{SlowRequest, SlowestRequests};
+use crate::{failure_category, Cancelled, Cli, Sample, FAILED_TRACES, TIMER_FLOOR};
+
+/// What the requests of a run returned, recorded by their tasks as they
+/// complete and summarized once the run is over
+pub struct Tally {
+    pub success_count: Mutex<usize>,
+    pub response_times: Mutex<Percentiles>,
+    pub latency_sum: Mutex<Duration>,
+    pub below_timer_floor: Mutex<usize>,
+    /// Responses of the successful requests by status
+    pub status_counts: Mutex<BTreeMap<StatusCode, usize>>,
+    pub failures: Mutex<BTreeMap<&'static str, usize>>,
+    pub slowest: Mutex<SlowestRequests>,
+    /// Index, failure category and trace ID of the first FAILED_TRACES failures
+    pub failed_traces: Mutex<Vec<(usize, &'static str, u128)>>,
+    pub response_sizes: Mutex<ResponseSizes>,
+    /// Response body bytes read by every request, failed ones included
+    pub bytes_received: Arc<AtomicUsize>,
+    pub error_messages: Mutex<ErrorMessages>,
+    pub cancelled: AtomicUsize,
+    /// Count and body bytes of the successful responses without an error status
+    pub goodput: Mutex<(usize, usize)>,
+    /// Latency and bytes transferred of every successful request
+    pub cost_samples: Option<Mutex<Vec<(Duration, u64)>>>,
+    /// Latency sum and count of the requests completed since the last --adaptive step
+    pub adapt_window: Option<Mutex<(Duration, u32)>>,
+    /// Latencies by status, with --latency-by-status
+    pub status_times: Option<Mutex<BTreeMap<StatusCode, (usize, Percentiles)>>>,
+    pub priority_times: Mutex<Vec<(usize, Percentiles)>>,
+    pub client_times: Mutex<Vec<(usize, Percentiles)>>,
+    pub phase_times: Mutex<Vec<(usize, Percentiles)>>,
+    percentiles: PercentileConfig,
+}
+
+/// What a request was, for the breakdowns it is recorded under
+pub struct RequestInfo {
+    pub index: usize,
+    /// Bytes of the request body
+    pub body_size: usize,
+    /// --priority class
+    pub priority: Option<usize>,
+    /// --replay-per-client client
+    pub client: Option<usize>,
+    /// --rate-schedule phase
+    pub phase: Option<usize>,
+    /// Picked by --detail-sample-rate to be reported individually
+    pub detailed: bool,
+    pub trace_id: Option<u128>,
+}
+
+impl Tally {
+    /// Empty tallies for a run of `cli` replaying `clients` clients over
+    /// `phases` schedule phases
+    pub fn new(cli: &Cli, clients: usize, phases: usize) -> Self {
+        let percentiles = cli.percentiles();
+        let breakdown = |len| {
+            Mutex::new(
+                (0..len)
+                    .map(|_| (0, Percentiles::new(percentiles)))
+                    .collect(),
+            )
+        };
+        Tally {
+            success_count: Mutex::new(0),
+            response_times: Mutex::new(Percentiles::new(percentiles)),
+            latency_sum: Mutex::new(Duration::ZERO),
+            below_timer_floor: Mutex::new(0),
+            status_counts: Mutex::new(BTreeMap::new()),
+            failures: Mutex::new(BTreeMap::new()),
+            slowest: Mutex::new(SlowestRequests::new(cli.top_slow)),
+            failed_traces: Mutex::new(Vec::new()),
+            response_sizes: Mutex::new(ResponseSizes::new(percentiles, cli.max_body_size)),
+            bytes_received: Arc::new(AtomicUsize::new(0)),
+            error_messages: Mutex::new(ErrorMessages::default()),
+            cancelled: AtomicUsize::new(0),
+            goodput: Mutex::new((0, 0)),
+            cost_samples: cli.cost_weighted.then(|| Mutex::new(Vec::new())),
+            adapt_window: cli.adaptive.then(|| Mutex::new((Duration::ZERO, 0))),
+            status_times: cli.latency_by_status.then(|| Mutex::new(BTreeMap::new())),
+            priority_times: breakdown(cli.priority.len()),
+            client_times: breakdown(clients),
+            phase_times: breakdown(phases),
+            percentiles,
+        }
+    }
+
+    /// Record a request that passed every check on its response.
+    pub fn success(&self, request: &RequestInfo, sample: &Sample) {
+        *self.success_count.lock().unwrap() += 1;
+        self.response_times.lock().unwrap().record(sample.duration);
+        *self.latency_sum.lock().unwrap() += sample.duration;
+        if sample.duration < TIMER_FLOOR {
+            *self.below_timer_floor.lock().unwrap() += 1;
+        }
+        *self
+            .status_counts
+            .lock()
+            .unwrap()
+            .entry(sample.status)
+            .or_default() += 1;
+        self.response_sizes.lock().unwrap().record(sample.body_size);
+        if !(sample.status.is_client_error() || sample.status.is_server_error()) {
+            let mut goodput = self.goodput.lock().unwrap();
+            goodput.0 += 1;
+            goodput.1 += sample.body_size;
+        }
+        if let Some(cost_samples) = &self.cost_samples {
+            let cost = (request.body_size + sample.body_size) as u64;
+            cost_samples.lock().unwrap().push((sample.duration, cost));
+        }
+        if let Some(window) = &self.adapt_window {
+            let mut window = window.lock().unwrap();
+            window.0 += sample.duration;
+            window.1 += 1;
+        }
+        if let Some(status_times) = &self.status_times {
+            let mut status_times = status_times.lock().unwrap();
+            let (count, times) = status_times
+                .entry(sample.status)
+                .or_insert_with(|| (0, Percentiles::new(self.percentiles)));
+            *count += 1;
+            times.record(sample.duration);
+        }
+        for (breakdown, entry) in [
+            (&self.priority_times, request.priority),
+            (&self.client_times, request.client),
+            (&self.phase_times, request.phase),
+        ] {
+            if let Some(entry) = entry {
+                let (count, times) = &mut breakdown.lock().unwrap()[entry];
+                *count += 1;
+                times.record(sample.duration);
+            }
+        }
+        if request.detailed {
+            self.slowest.lock().unwrap().record(SlowRequest {
+                duration: sample.duration,
+                index: request.index,
+                status: sample.status,
+                trace_id: request.trace_id,
+            });
+        }
+    }
+
+    /// Record a request that failed with `err`, returning the category it
+    /// is reported under.
+    pub fn failure(&self, request: &RequestInfo, err: &anyhow::Error) -> &'static str {
+        let category = failure_category(err);
+        // Cancelled requests are slow rather than failed
+        if err.is::<Cancelled>() {
+            self.cancelled.fetch_add(1, Ordering::SeqCst);
+        } else {
+            *self.failures.lock().unwrap().entry(category).or_default() += 1;
+            self.error_messages.lock().unwrap().record(err);
+        }
+        if let Some(trace_id) = request.trace_id.filter(|_| request.detailed) {
+            let mut failed_traces = self.failed_traces.lock().unwrap();
+            if failed_traces.len() < FAILED_TRACES {
+                failed_traces.push((request.index, category, trace_id));
+            }
+        }
+        category
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpStream;
+
+/// `tcpi_state` of an established connection, from the kernel's tcp_states.h
+#[cfg(target_os = "linux")]
+const TCP_ESTABLISHED: u8 = 1;
+
+/// Socket statistics read with `TCP_INFO` from the connections of a run.
+/// Only Linux exposes them; elsewhere nothing is collected.
+#[derive(Default)]
+pub struct TcpInfoStats {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Open connections, by the ID of their [`Watch`]. A probe is removed
+    /// before its socket closes, so its descriptor is never stale.
+    probes: BTreeMap<usize, Probe>,
+    next_id: usize,
+    /// Retransmits of the connections that have closed
+    closed_retransmits: u32,
+    rtt_sum: Duration,
+    rtt_samples: usize,
+    max_cwnd: u32,
+}
+
+// Only constructed where sockets can be watched
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Probe {
+    /// The connection's own socket, owned by its [`Watched`] stream
+    #[cfg(target_os = "linux")]
+    fd: std::os::fd::RawFd,
+    /// Retransmits reported by the latest sample
+    retransmits: u32,
+}
+
+/// Aggregated socket statistics
+pub struct TcpInfoSummary {
+    pub average_rtt: Duration,
+    pub retransmits: u32,
+    pub max_cwnd: u32,
+}
+
+/// Sampling of one connection's socket, which stops when this is dropped.
+/// The dropped watch takes a last sample.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Watch {
+    inner: Arc<Mutex<Inner>>,
+    id: usize,
+}
+
+/// A connection's stream, sampled for `--tcp-info` while it is open. The
+/// watch is dropped before the stream closes the socket, so sampling never
+/// needs a descriptor of its own.
+pub struct Watched {
+    // Only held to be dropped, before the stream as it is declared first
+    _watch: Option<Watch>,
+    stream: TokioIo<TcpStream>,
+}
+
+impl Watched {
+    /// Wrap `stream` for hyper, sampling its socket into `stats` if given.
+    pub fn new(stream: TcpStream, stats: Option<&TcpInfoStats>) -> Self {
+        Watched {
+            _watch: stats.and_then(|stats| stats.watch(&stream)),
+            stream: TokioIo::new(stream),
+        }
+    }
+}
+
+impl Read for Watched {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl Write for Watched {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write_vectored(cx, bufs)
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let mut inner = self.inner.lock().unwrap();
+        #[cfg(target_os = "linux")]
+        inner.sample(Some(self.id));
+        if let Some(probe) = inner.probes.remove(&self.id) {
+            inner.closed_retransmits += probe.retransmits;
+        }
+    }
+}
+
+impl TcpInfoStats {
+    /// Start sampling the socket of `stream`, until the returned watch is
+    /// dropped, which must happen before the socket closes.
+    fn watch(&self, stream: &TcpStream) -> Option<Watch> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::fd::AsRawFd;
+
+            let mut inner = self.inner.lock().unwrap();
+            let id = inner.next_id;
+            inner.next_id += 1;
+            let fd = stream.as_raw_fd();
+            inner.probes.insert(id, Probe { fd, retransmits: 0 });
+            Some(Watch {
+                inner: self.inner.clone(),
+                id,
+            })
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = stream;
+            None
+        }
+    }
+
+    /// Read `TCP_INFO` from every watched socket.
+    pub fn sample(&self) {
+        #[cfg(target_os = "linux")]
+        self.inner.lock().unwrap().sample(None);
+    }
+
+    /// Summarize the samples taken so far, or `None` if there are none.
+    pub fn summary(&self) -> Option<TcpInfoSummary> {
+        let inner = self.inner.lock().unwrap();
+        if inner.rtt_samples == 0 {
+            return None;
+        }
+
+        Some(TcpInfoSummary {
+            average_rtt: inner.rtt_sum / inner.rtt_samples as u32,
+            retransmits: inner.closed_retransmits
+                + inner
+                    .probes
+                    .values()
+                    .map(|probe| probe.retransmits)
+                    .sum::<u32>(),
+            max_cwnd: inner.max_cwnd,
+        })
+    }
+}
+
+impl Inner {
+    /// Read `TCP_INFO` from the socket of watch `only`, or from every socket.
+    #[cfg(target_os = "linux")]
+    fn sample(&mut self, only: Option<usize>) {
+        let Inner {
+            probes,
+            rtt_sum,
+            rtt_samples,
+            max_cwnd,
+            ..
+        } = self;
+
+        for (id, probe) in probes.iter_mut() {
+            if only.is_some_and(|only| only != *id) {
+                continue;
+            }
+            let Some(info) = read_tcp_info(probe.fd) else {
+                continue;
+            };
+            // Closed sockets keep their last counters but no longer have an RTT
+            if info.tcpi_state == TCP_ESTABLISHED {
+                *rtt_sum += Duration::from_micros(info.tcpi_rtt as u64);
+                *rtt_samples += 1;
+            }
+            probe.retransmits = info.tcpi_total_retrans;
+            *max_cwnd = (*max_cwnd).max(info.tcpi_snd_cwnd);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_tcp_info(fd: std::os::fd::RawFd) -> Option<libc::tcp_info> {
+    let mut info = std::mem::MaybeUninit::<libc::tcp_info>::zeroed();
+    let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+    // SAFETY: the kernel writes at most `len` bytes into the zeroed struct
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            info.as_mut_ptr().cast(),
+            &mut len,
+        )
+    };
+    // SAFETY: the struct started zeroed and is plain old data
+    (result == 0).then(|| unsafe { info.assume_init() })
+}
@@ -0,0 +1,117 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::time::sleep;
+
+/// Time worth of tokens the bucket holds at most. A few timer ticks, so
+/// the tokens refilled while a sleep overshoots to the next millisecond are
+/// all kept even at high rates.
+const CAPACITY_SPAN: Duration = Duration::from_millis(5);
+
+/// Tokens the bucket holds at most at low rates, where a span holds less
+const MIN_CAPACITY: f64 = 2.0;
+
+/// Token bucket pacing dispatches to a global rate. Tokens left over when
+/// a sleep runs long carry over and are spent without sleeping, so timer
+/// granularity doesn't lower the average rate, while a stalled dispatcher
+/// catches up with at most `CAPACITY_SPAN` worth of requests instead of a
+/// burst.
+pub struct TokenBucket {
+    state: Mutex<State>,
+}
+
+struct State {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A bucket refilling at `rate` tokens per second, starting empty so the
+    /// first token is available one interval from now.
+    pub fn new(rate: f64) -> Self {
+        TokenBucket {
+            state: Mutex::new(State {
+                rate,
+                capacity: capacity(rate),
+                tokens: 0.0,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Change the refill rate, e.g. for the next phase of a schedule.
+    pub fn set_rate(&self, rate: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.refill();
+        state.rate = rate;
+        state.capacity = capacity(rate);
+    }
+
+    /// Wait until a token is available and take it.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                state.refill();
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.tokens) / state.rate)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+impl State {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+}
+
+fn capacity(rate: f64) -> f64 {
+    (rate * CAPACITY_SPAN.as_secs_f64()).max(MIN_CAPACITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_covers_a_few_ticks() {
+        assert_eq!(capacity(10.0), MIN_CAPACITY);
+        assert_eq!(capacity(20_000.0), 100.0);
+    }
+
+    #[tokio::test]
+    async fn keeps_up_with_rates_above_the_timer_resolution() {
+        // Acquiring one token per sleep would top out around 1000/s
+        let bucket = TokenBucket::new(20_000.0);
+        let start = Instant::now();
+        for _ in 0..2_000 {
+            bucket.acquire().await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(95), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(300), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn stall_bursts_at_most_the_capacity() {
+        let bucket = TokenBucket::new(1_000.0);
+        sleep(Duration::from_millis(50)).await;
+        let start = Instant::now();
+        for _ in 0..5 {
+            bucket.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(2));
+        bucket.acquire().await;
+        assert!(start.elapsed() >= Duration::from_micros(500));
+    }
+}
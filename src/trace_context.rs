@@ -0,0 +1,70 @@
+use hyper::header::{HeaderName, HeaderValue};
+use rand::Rng;
+
+pub const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+pub const TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+
+/// Generate a W3C `traceparent` for a new, sampled trace, returning the
+/// trace ID alongside the header value. All-zero IDs are invalid, so both
+/// IDs are drawn from the non-zero range.
+pub fn traceparent(rng: &mut impl Rng) -> (u128, HeaderValue) {
+    let trace_id = rng.gen_range(1..=u128::MAX);
+    let span_id = rng.gen_range(1..=u64::MAX);
+    let value = format!("00-{:032x}-{:016x}-01", trace_id, span_id);
+    (trace_id, HeaderValue::from_str(&value).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// The trace and span IDs of a `traceparent`, checking its layout
+    fn parse(value: &HeaderValue) -> (u128, u64) {
+        let fields: Vec<&str> = value.to_str().unwrap().split('-').collect();
+        let [version, trace_id, span_id, flags] = fields[..] else {
+            panic!("{:?} doesn't have 4 fields", value);
+        };
+        assert_eq!((version, flags), ("00", "01"));
+        assert_eq!((trace_id.len(), span_id.len()), (32, 16));
+        let hex = |id: &str| id.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f'));
+        assert!(hex(trace_id) && hex(span_id), "{:?}", value);
+        (
+            u128::from_str_radix(trace_id, 16).unwrap(),
+            u64::from_str_radix(span_id, 16).unwrap(),
+        )
+    }
+
+    #[test]
+    fn valid_ids() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let (trace_id, value) = traceparent(&mut rng);
+            let (parsed, span_id) = parse(&value);
+            assert_eq!(parsed, trace_id);
+            assert_ne!(span_id, 0);
+        }
+    }
+
+    #[test]
+    fn never_all_zero() {
+        // An RNG that only ever yields zero bits
+        let (_, value) = traceparent(&mut StepRng::new(0, 0));
+        let (trace_id, span_id) = parse(&value);
+        assert_ne!(trace_id, 0);
+        assert_ne!(span_id, 0);
+    }
+
+    #[test]
+    fn same_seed_same_header() {
+        let mut first = StdRng::seed_from_u64(42);
+        let mut second = StdRng::seed_from_u64(42);
+        for _ in 0..10 {
+            assert_eq!(traceparent(&mut first), traceparent(&mut second));
+        }
+        let other = traceparent(&mut StdRng::seed_from_u64(43));
+        assert_ne!(traceparent(&mut StdRng::seed_from_u64(42)), other);
+    }
+}
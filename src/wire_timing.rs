@@ -0,0 +1,48 @@
+use std::time::Instant;
+
+use hyper::StatusCode;
+
+use crate::{output, Timeline};
+
+/// `--wire-timing` line for a completed request, with every stage as
+/// nanoseconds since `origin`, the start of the run
+pub fn success(index: usize, origin: Instant, timeline: &Timeline, status: StatusCode) -> String {
+    format!(
+        r#"{{"index":{},"queued_ns":{},"send_start_ns":{},"headers_ns":{},"body_complete_ns":{},"status":{}}}"#,
+        index,
+        offset(origin, timeline.enqueued),
+        offset(origin, timeline.sent),
+        offset(origin, timeline.headers),
+        offset(origin, timeline.completed),
+        status.as_u16()
+    )
+}
+
+/// `--wire-timing` line for a failed request, of which only the time it was
+/// queued is known
+pub fn failure(index: usize, origin: Instant, enqueued: Instant, category: &str) -> String {
+    format!(
+        r#"{{"index":{},"queued_ns":{},"error":{}}}"#,
+        index,
+        offset(origin, enqueued),
+        output::string(category)
+    )
+}
+
+fn offset(origin: Instant, at: Instant) -> u128 {
+    at.saturating_duration_since(origin).as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn failure_category_is_escaped() {
+        let origin = Instant::now();
+        let line = failure(2, origin, origin + Duration::from_nanos(50), "bad \"x\"\n");
+        assert_eq!(line, r#"{"index":2,"queued_ns":50,"error":"bad \"x\"\n"}"#);
+    }
+}